@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use recorder::pipeline_processing::{
+    frame::{BitPacking, FrameInterpretation, FrameRate, Raw},
+    unpack::{unpack, unpack_rows, unpack_scalar},
+};
+
+// a frame of the AXIOM Beta
+const WIDTH: u64 = 4096;
+const HEIGHT: u64 = 3072;
+
+fn unpack_benchmark(c: &mut Criterion) {
+    for &bit_depth in [10, 12, 14].iter() {
+        let interp = Raw {
+            width: WIDTH,
+            height: HEIGHT,
+            bit_depth,
+            packing: BitPacking::MsbFirst,
+            cfa: "RGGB".parse().unwrap(),
+            fps: FrameRate::new(24, 1).unwrap(),
+        };
+        let input: Vec<u8> = (0..interp.required_bytes()).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut samples = vec![0u16; (WIDTH * HEIGHT) as usize];
+        let mut output = vec![0u8; samples.len() * 2];
+
+        let mut group = c.benchmark_group(format!("unpack {} bit", bit_depth));
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.sample_size(20);
+        group.bench_function("scalar", |b| {
+            b.iter(|| unpack_scalar(interp.packing, bit_depth, &input, &mut samples))
+        });
+        group.bench_function("specialized", |b| {
+            b.iter(|| unpack(interp.packing, bit_depth, &input, &mut samples))
+        });
+        group.bench_function("rows in parallel", |b| {
+            b.iter(|| {
+                unpack_rows(&interp, &input, &mut output, 16, |_, samples, output| {
+                    for (&sample, output) in samples.iter().zip(output.chunks_exact_mut(2)) {
+                        output.copy_from_slice(&sample.to_le_bytes());
+                    }
+                })
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, unpack_benchmark);
+criterion_main!(benches);
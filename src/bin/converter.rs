@@ -4,107 +4,296 @@ use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
 use recorder::pipeline_processing::{
     create_node_from_name,
-    execute::{execute_pipeline, ProcessingStageLockWaiter},
+    error_policy::ErrorPolicy,
+    execute::{start_pipeline, ExecutionOptions, ExecutionSummary},
+    graph::{NodeId, ProcessingGraph},
     list_available_nodes,
+    output_type_of_node,
     parametrizable::{
         ParameterTypeDescriptor::{Mandatory, Optional},
         ParameterizableDescriptor,
         Parameters,
     },
-    payload::Payload,
+    pipeline_description::{graph_from_description, graph_to_description, DescriptionFormat},
+    port_type::PortType,
     processing_context::ProcessingContext,
     processing_node::ProcessingNode,
+    progress::Progress,
 };
 use std::{
     collections::HashMap,
     env,
-    iter::{once, FromIterator},
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-        RwLock,
-    },
-    time::SystemTime,
+    fs,
+    iter::{once, Peekable},
+    path::Path,
+    slice::Iter,
+    thread,
+    time::{Duration, Instant},
 };
 
 fn main() {
     let res = work();
+    if let Ok(Some(summary)) = &res {
+        if !summary.failed_frames.is_empty() {
+            eprintln!("\n{} frames had errors:", summary.failed_frames.len());
+            for failure in &summary.failed_frames {
+                eprintln!("    {}", failure);
+            }
+        }
+    }
     match res {
-        Ok(_) => eprintln!("\nconversion successfully finished :)"),
+        Ok(None) => {}
+        Ok(Some(summary)) if summary.interrupted => eprintln!(
+            "\nconversion stopped after {} frames in {:.1}s",
+            summary.frames,
+            summary.duration.as_secs_f64()
+        ),
+        Ok(Some(summary)) => eprintln!(
+            "\nconversion successfully finished :) ({} frames in {:.1}s)",
+            summary.frames,
+            summary.duration.as_secs_f64()
+        ),
         Err(error) => {
             eprintln!("An error occured: \n{}", error.chain().map(|e| format!("{}", e)).join("\n"))
         }
     }
 }
 
-// used to have the convenience of ? for error handling
-fn work() -> Result<()> {
+// used to have the convenience of ? for error handling. returns None if no
+// pipeline was run
+fn work() -> Result<Option<ExecutionSummary>> {
     let args: Vec<String> = env::args().collect();
-    let arg_blocks: Vec<Vec<&String>> = args.split(|s| s == "!").map(Vec::from_iter).collect();
+    let mut tokens = args.iter().peekable();
+    let app_arguments: Vec<&String> =
+        tokens.peeking_take_while(|t| *t != CHAIN_SEPARATOR).collect();
 
-    let _main_app_arguments = App::new("Raw Image / Video Converter")
-        .usage("converter [--app-args] ! <VideoSource> --source arg ! <VideoSink> --sink arg")
+    let main_app_arguments = App::new("Raw Image / Video Converter")
+        .usage(
+            "converter [--app-args] ! <VideoSource> --source arg ! <VideoSink> --sink arg\n    \
+             converter [--app-args] --pipeline <FILE>",
+        )
         .about("convert raw footage from AXIOM cameras into other formats.")
-        .after_help(format!("NODES:\n{}", nodes_usages_string()).as_str())
-        .get_matches_from(&arg_blocks[0]);
+        .arg(
+            Arg::with_name("max-frames-in-flight")
+                .long("max-frames-in-flight")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("the maximum number of frames processed at the same time"),
+        )
+        .arg(
+            Arg::with_name("memory-budget")
+                .long("memory-budget")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("pause reading while the frames in flight use more than this many MiB"),
+        )
+        .arg(
+            Arg::with_name("on-error")
+                .long("on-error")
+                .takes_value(true)
+                .possible_values(&["abort", "skip", "retry", "previous", "black"])
+                .default_value("abort")
+                .help("what to do with frames that fail in a node"),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .takes_value(true)
+                .default_value("3")
+                .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("how often a failed frame is retried with --on-error retry (sources are never retried)"),
+        )
+        .arg(
+            Arg::with_name("pipeline")
+                .long("pipeline")
+                .takes_value(true)
+                .help("read the pipeline from a toml or json file instead of the command line"),
+        )
+        .arg(
+            Arg::with_name("dump-pipeline")
+                .long("dump-pipeline")
+                .takes_value(true)
+                .possible_values(&["toml", "json"])
+                .help("print the pipeline in the given format instead of running it"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .possible_values(&["table", "json"])
+                .help("print the time every node spent on frames at the end"),
+        )
+        .arg(
+            Arg::with_name("profile-interval")
+                .long("profile-interval")
+                .takes_value(true)
+                .requires("profile")
+                .validator(|v| v.parse::<f64>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("also print the profile every this many seconds"),
+        )
+        .after_help(
+            format!(
+                "BRANCHES:\n    {}\n\nNODES:\n{}",
+                "converter ! <VideoSource> ! { <VideoSink> } { <Node> ! <VideoSink> }",
+                nodes_usages_string()
+            )
+            .as_str(),
+        )
+        .get_matches_from(app_arguments);
+
+    let graph = match main_app_arguments.value_of("pipeline") {
+        Some(path) => {
+            if let Some(token) = tokens.next() {
+                return Err(anyhow!("unexpected {} in addition to --pipeline", token));
+            }
+            let description = fs::read_to_string(path)
+                .with_context(|| format!("cant read pipeline description {}", path))?;
+            graph_from_description(&description, DescriptionFormat::from_path(Path::new(path))?)
+                .with_context(|| format!("invalid pipeline description {}", path))?
+        }
+        None => parse_pipeline(&mut tokens)?
+            .try_map(|_, arg_block| parameters_from_commandline(&arg_block))?,
+    };
+    check_port_types(&graph)?;
+    if let Some(format) = main_app_arguments.value_of("dump-pipeline") {
+        print!("{}", graph_to_description(&graph, format.parse()?)?);
+        return Ok(None);
+    }
 
+    // only created after dumping the pipeline, as it prints the device it uses to
+    // stdout
     let processing_context = ProcessingContext::default();
 
-    let mut nodes = arg_blocks[1..]
-        .iter()
-        .map(|arg_block| processing_node_from_commandline(arg_block, processing_context.clone()))
-        .collect::<Result<Vec<_>>>()?;
-    nodes.push(Arc::new(ProgressNode::new(nodes[0].size_hint())));
+    let error_policy = match main_app_arguments.value_of("on-error").unwrap() {
+        "abort" => ErrorPolicy::Abort,
+        "skip" => ErrorPolicy::Skip,
+        "retry" => ErrorPolicy::Retry(main_app_arguments.value_of("retries").unwrap().parse()?),
+        "previous" => ErrorPolicy::SubstitutePrevious,
+        "black" => ErrorPolicy::SubstituteBlack(processing_context.clone()),
+        _ => unreachable!(),
+    };
+    let execution_options = ExecutionOptions {
+        max_frames_in_flight: main_app_arguments
+            .value_of("max-frames-in-flight")
+            .map(|v| v.parse())
+            .transpose()?,
+        memory_budget: main_app_arguments
+            .value_of("memory-budget")
+            .map(|v| v.parse::<usize>().map(|mib| mib * 1024 * 1024))
+            .transpose()?,
+        stop_on_signal: true,
+        error_policy,
+        ..ExecutionOptions::default()
+    };
 
-    execute_pipeline(nodes)?;
+    let graph = graph.try_map(|name, parameters| {
+        create_node_from_name(name, &parameters, processing_context.clone())
+            .with_context(|| format!("Error while creating Node {}", name))
+    })?;
+    let progressbar = progressbar(graph.get(graph.source()).node.size_hint());
 
-    Ok(())
+    let format_profile = |progress: &Progress| match main_app_arguments.value_of("profile") {
+        Some("json") => Some(progress.profile_json()),
+        Some(_) => Some(progress.profile_table()),
+        None => None,
+    };
+    let profile_interval = main_app_arguments
+        .value_of("profile-interval")
+        .map(|v| v.parse().map(Duration::from_secs_f64))
+        .transpose()?;
+
+    let pipeline = start_pipeline(graph, execution_options)?;
+    let mut last_profile = Instant::now();
+    while !pipeline.is_finished() {
+        let progress = pipeline.progress();
+        progressbar.set_position(progress.frames_done);
+        match progress.frames_failed {
+            0 => progressbar.set_message(format!("{:.1} fps", progress.fps)),
+            failed => progressbar
+                .set_message(format!("{:.1} fps, {} failed frames", progress.fps, failed)),
+        }
+        if profile_interval.is_some_and(|interval| last_profile.elapsed() >= interval) {
+            progressbar.println(format_profile(&progress).unwrap_or_default());
+            last_profile = Instant::now();
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    progressbar.finish();
+    if let Some(profile) = format_profile(&pipeline.progress()) {
+        eprintln!("\n{}", profile.trim_end());
+    }
+
+    pipeline.join().map(Some)
 }
 
-struct ProgressNode {
-    progressbar: ProgressBar,
-    start_time: RwLock<SystemTime>,
-    fps_counter: AtomicU64,
+const CHAIN_SEPARATOR: &str = "!";
+const BRANCH_START: &str = "{";
+const BRANCH_END: &str = "}";
+
+// nodes are chained with `!`. the end of a chain can fan out into several
+// branches, which are chains in braces themselves:
+// `! Source ! Node ! { Sink } { OtherNode ! OtherSink }`
+fn parse_pipeline<'a>(
+    tokens: &mut Peekable<Iter<'a, String>>,
+) -> Result<ProcessingGraph<Vec<&'a String>>> {
+    tokens.next_if(|t| *t == CHAIN_SEPARATOR);
+    let source = take_node_arguments(tokens);
+    if source.is_empty() {
+        return Err(anyhow!("the pipeline has to start with a source node"));
+    }
+
+    let mut graph = ProcessingGraph::new(source[0], source);
+    let source = graph.source();
+    parse_chain(tokens, &mut graph, source)?;
+    match tokens.next() {
+        None => Ok(graph),
+        Some(token) => Err(anyhow!("unexpected {} in pipeline", token)),
+    }
 }
-impl ProgressNode {
-    fn new(total: Option<u64>) -> ProgressNode {
-        let progressbar = match total {
-            Some(n) => {
-                let bar = ProgressBar::new(n as u64);
-                bar.set_style(ProgressStyle::default_bar()
-                    .template("{wide_bar} | {pos}/{len} frames | elapsed: {elapsed_precise} | remaining: {eta} | {msg} ")
-                    .progress_chars("#>-"));
-                bar
+fn parse_chain<'a>(
+    tokens: &mut Peekable<Iter<'a, String>>,
+    graph: &mut ProcessingGraph<Vec<&'a String>>,
+    mut input: NodeId,
+) -> Result<()> {
+    loop {
+        match tokens.peek().map(|t| t.as_str()) {
+            None | Some(BRANCH_END) => return Ok(()),
+            Some(CHAIN_SEPARATOR) => {
+                tokens.next();
+            }
+            Some(BRANCH_START) => {
+                while tokens.next_if(|t| *t == BRANCH_START).is_some() {
+                    parse_chain(tokens, graph, input)?;
+                    tokens
+                        .next_if(|t| *t == BRANCH_END)
+                        .ok_or_else(|| anyhow!("unclosed branch, expected {}", BRANCH_END))?;
+                }
+                // branches always end a chain
+                return Ok(());
+            }
+            Some(_) => {
+                let node = take_node_arguments(tokens);
+                input = graph.add_node(node[0], node, input)?;
             }
-            None => ProgressBar::new_spinner(),
-        };
-
-        ProgressNode {
-            progressbar,
-            start_time: RwLock::new(SystemTime::now()),
-            fps_counter: AtomicU64::new(0),
         }
     }
 }
-impl ProcessingNode for ProgressNode {
-    fn process(
-        &self,
-        _input: &mut Payload,
-        _frame_lock: ProcessingStageLockWaiter,
-    ) -> Result<Option<Payload>> {
-        self.progressbar.inc(1);
-        self.fps_counter.fetch_add(1, Ordering::Relaxed);
-
-        let time = SystemTime::now();
-        let elapsed = time.duration_since(*self.start_time.read().unwrap()).unwrap().as_secs_f64();
-        if elapsed > 1.0 {
-            self.progressbar.set_message(format!(
-                "{:.1} fps",
-                self.fps_counter.swap(0, Ordering::Relaxed) as f64 / elapsed
-            ));
-            *self.start_time.write().unwrap() = time;
+fn take_node_arguments<'a>(tokens: &mut Peekable<Iter<'a, String>>) -> Vec<&'a String> {
+    tokens
+        .peeking_take_while(|t| ![CHAIN_SEPARATOR, BRANCH_START, BRANCH_END].contains(&t.as_str()))
+        .collect()
+}
+
+fn progressbar(total: Option<u64>) -> ProgressBar {
+    match total {
+        Some(n) => {
+            let bar = ProgressBar::new(n as u64);
+            bar.set_style(ProgressStyle::default_bar()
+                .template("{wide_bar} | {pos}/{len} frames | elapsed: {elapsed_precise} | remaining: {eta} | {msg} ")
+                .progress_chars("#>-"));
+            bar
         }
-        Ok(Some(Payload::empty()))
+        None => ProgressBar::new_spinner(),
     }
 }
 
@@ -173,10 +362,7 @@ fn nodes_usages_string() -> String {
         })
         .join("\n")
 }
-fn processing_node_from_commandline(
-    commandline: &[&String],
-    context: ProcessingContext,
-) -> Result<Arc<dyn ProcessingNode>> {
+fn parameters_from_commandline(commandline: &[&String]) -> Result<Parameters> {
     let name = commandline[0];
 
     let available_nodes: HashMap<String, ParameterizableDescriptor> = list_available_nodes();
@@ -195,14 +381,30 @@ fn processing_node_from_commandline(
     let results = app
         .get_matches_from_safe(commandline)
         .with_context(|| format!("Wrong Parameters for Node {}", name))?;
-    let parameters: HashMap<_, _> = parameters_description
+    let parameters = parameters_description
         .0
         .iter()
         .map(|(key, parameter_type)| {
             Ok((key.to_string(), parameter_type.parse(results.value_of(key))?))
         })
         .collect::<Result<_, anyhow::Error>>()?;
+    Ok(Parameters(parameters))
+}
 
-    create_node_from_name(name, &Parameters(parameters), context)
-        .with_context(|| format!("Error while creating Node {}", name))
+// checks that every node accepts the output of its input node before any node
+// is created, so a wrong pipeline fails before files are opened or processes
+// spawned
+fn check_port_types(graph: &ProcessingGraph<Parameters>) -> Result<()> {
+    let mut output_types: Vec<PortType> = Vec::with_capacity(graph.len());
+    for (_, node) in graph.iter() {
+        let input_type = node.input.map_or(PortType::Empty, |input| output_types[input]);
+        let connection = || match node.input {
+            Some(input) => format!("cant connect {} to {}", graph.get(input).name, node.name),
+            None => format!("cant use {} as the source of the pipeline", node.name),
+        };
+        let output_type =
+            output_type_of_node(&node.name, &node.node, input_type).with_context(connection)?;
+        output_types.push(output_type);
+    }
+    Ok(())
 }
@@ -1,2 +1 @@
 pub mod formatting_helpers;
-pub mod fps_report;
@@ -1,5 +1,6 @@
 pub mod common;
 pub mod nodes_cpu;
+#[cfg(feature = "gpu")]
 pub mod nodes_gpu;
 pub mod nodes_io;
 pub mod pipeline_processing;
@@ -1,28 +1,126 @@
 use crate::pipeline_processing::{
     buffers::CpuBuffer,
     execute::ProcessingStageLockWaiter,
-    parametrizable::{Parameterizable, Parameters, ParametersDescriptor},
+    parametrizable::{
+        ParameterType::{BoolParameter, StringParameter},
+        ParameterTypeDescriptor::Optional,
+        ParameterValue,
+        Parameterizable,
+        Parameters,
+        ParametersDescriptor,
+    },
     payload::Payload,
+    port_type::PortType,
     processing_node::ProcessingNode,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 
 use crate::pipeline_processing::{
-    frame::{Frame, Raw},
+    frame::{BitPacking, Frame, FrameInterpretation, Raw},
     processing_context::ProcessingContext,
+    unpack::unpack_rows,
 };
 
+/// What `BitDepthConverter` and `GpuBitDepthConverter` turn the samples into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepthConversion {
+    /// one byte per sample, dropping the lower bits
+    Truncate8Bit,
+    /// every sample in the low bits of its own 16 bit word, keeping the bit
+    /// depth
+    Expand16Bit,
+    /// every sample in its own 16 bit word, shifted up to use the whole 16 bit
+    LeftAlign16Bit,
+}
+
+impl BitDepthConversion {
+    pub fn describe_parameters(descriptor: ParametersDescriptor) -> ParametersDescriptor {
+        descriptor
+            .with(
+                "output",
+                Optional(StringParameter, ParameterValue::StringParameter("8bit".to_string())),
+            )
+            .with("left-align", Optional(BoolParameter, ParameterValue::BoolParameter(false)))
+    }
+
+    pub fn from_parameters(parameters: &Parameters) -> Result<Self> {
+        let left_align = parameters.get::<bool>("left-align")?;
+        match (parameters.get::<String>("output")?.as_str(), left_align) {
+            ("8bit", false) => Ok(BitDepthConversion::Truncate8Bit),
+            ("8bit", true) => Err(anyhow!("left-align only makes sense with 16 bit output")),
+            ("16bit", false) => Ok(BitDepthConversion::Expand16Bit),
+            ("16bit", true) => Ok(BitDepthConversion::LeftAlign16Bit),
+            (output, _) => Err(anyhow!("unknown output {}, expected 8bit or 16bit", output)),
+        }
+    }
+
+    /// The type of the converted frames for inputs of the type `input`
+    pub fn output_type(self, input: PortType) -> PortType {
+        match self {
+            BitDepthConversion::Truncate8Bit => PortType::raw(8),
+            BitDepthConversion::Expand16Bit => match input {
+                PortType::Raw { bit_depth } => PortType::Raw { bit_depth },
+                _ => PortType::any_raw(),
+            },
+            BitDepthConversion::LeftAlign16Bit => PortType::raw(16),
+        }
+    }
+
+    pub fn output_interp(self, input: Raw) -> Raw {
+        match self {
+            BitDepthConversion::Truncate8Bit => {
+                Raw { bit_depth: 8, packing: BitPacking::MsbFirst, ..input }
+            }
+            BitDepthConversion::Expand16Bit => Raw { packing: BitPacking::U16Le, ..input },
+            BitDepthConversion::LeftAlign16Bit => {
+                Raw { bit_depth: 16, packing: BitPacking::U16Le, ..input }
+            }
+        }
+    }
+
+    /// Whether frames with this interpretation already are what this
+    /// conversion would produce
+    pub fn is_noop(self, input: &Raw) -> bool {
+        let output = self.output_interp(*input);
+        input.bit_depth == output.bit_depth && input.packing == output.packing
+    }
+
+    /// The shift from an input sample to an output sample. Positive values
+    /// shift left.
+    pub fn shift(self, bit_depth: u64) -> i64 {
+        match self {
+            BitDepthConversion::Truncate8Bit => 8 - bit_depth as i64,
+            BitDepthConversion::Expand16Bit => 0,
+            BitDepthConversion::LeftAlign16Bit => 16 - bit_depth as i64,
+        }
+    }
+}
+
 pub struct BitDepthConverter {
+    conversion: BitDepthConversion,
     context: ProcessingContext,
 }
+crate::register_node!(BitDepthConverter);
 impl Parameterizable for BitDepthConverter {
-    fn describe_parameters() -> ParametersDescriptor { ParametersDescriptor::new() }
+    const DESCRIPTION: Option<&'static str> = Some(
+        "convert packed raw frames to 8 bit samples or to samples in 16 bit words (output=16bit)",
+    );
 
-    fn from_parameters(_parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    fn describe_parameters() -> ParametersDescriptor {
+        BitDepthConversion::describe_parameters(ParametersDescriptor::new())
+    }
+
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(parameters: &Parameters, input: PortType) -> Result<PortType> {
+        Ok(BitDepthConversion::from_parameters(parameters)?.output_type(input))
+    }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
     {
-        Ok(Self { context })
+        Ok(Self { conversion: BitDepthConversion::from_parameters(parameters)?, context })
     }
 }
 impl ProcessingNode for BitDepthConverter {
@@ -32,55 +130,43 @@ impl ProcessingNode for BitDepthConverter {
         _frame_lock: ProcessingStageLockWaiter,
     ) -> Result<Option<Payload>> {
         let frame = input.downcast::<Frame<Raw, CpuBuffer>>().context("Wrong input format")?;
-        let mut new_buffer = unsafe {
-            self.context.get_uninit_cpu_buffer((frame.interp.width * frame.interp.height) as usize)
-        };
-
-        if frame.interp.bit_depth == 8 {
+        let interp = frame.interp;
+        if self.conversion.is_noop(&interp) {
             return Ok(Some(input.clone()));
-        } else if frame.interp.bit_depth == 12 {
-            new_buffer.as_mut_slice(|new_buffer| {
-                frame.storage.as_slice(|frame_storage| {
-                    for (input, output) in
-                        frame_storage.chunks_exact(3).zip(new_buffer.chunks_exact_mut(2))
-                    {
-                        output[0] = input[0];
-                        output[1] = (input[1] << 4) | (input[2] >> 4);
-                    }
-                })
-            });
-        } else {
-            let mut rest_value: u32 = 0;
-            let mut rest_bits: u32 = 0;
-            let mut pos = 0;
-            new_buffer.as_mut_slice(|new_buffer| {
-                frame.storage.as_slice(|frame_storage| {
-                    for value in frame_storage.iter() {
-                        let bits_more_than_bit_depth =
-                            (rest_bits as i32 + 8) - frame.interp.bit_depth as i32;
-                        if bits_more_than_bit_depth >= 0 {
-                            let new_n_bit_value: u32 = rest_value
-                                .wrapping_shl(frame.interp.bit_depth as u32 - rest_bits)
-                                | value.wrapping_shr(8 - bits_more_than_bit_depth as u32) as u32;
-                            new_buffer[pos] = (if frame.interp.bit_depth > 8 {
-                                new_n_bit_value.wrapping_shr(frame.interp.bit_depth as u32 - 8)
-                            } else {
-                                new_n_bit_value
-                            } as u8);
-                            pos += 1;
-
-                            rest_bits = bits_more_than_bit_depth as u32;
-                            rest_value = (value & (2u32.pow(rest_bits as u32) - 1) as u8) as u32
-                        } else {
-                            rest_bits += 8;
-                            rest_value = (rest_value << 8) | *value as u32;
-                        };
-                    }
-                })
-            });
         }
 
-        let new_frame = Frame { storage: new_buffer, interp: Raw { bit_depth: 8, ..frame.interp } };
+        let output_interp = self.conversion.output_interp(interp);
+        let mut new_buffer =
+            unsafe { self.context.get_uninit_cpu_buffer(output_interp.required_bytes()) };
+
+        let shift = self.conversion.shift(interp.bit_depth);
+        let convert = move |sample: u16| {
+            if shift >= 0 {
+                sample << shift
+            } else {
+                sample >> -shift
+            }
+        };
+        new_buffer.as_mut_slice(|new_buffer| {
+            frame.storage.as_slice(|frame_storage| {
+                if output_interp.packing == BitPacking::U16Le {
+                    unpack_rows(&interp, frame_storage, new_buffer, 16, |_, samples, output| {
+                        for (&sample, output) in samples.iter().zip(output.chunks_exact_mut(2)) {
+                            output.copy_from_slice(&convert(sample).to_le_bytes());
+                        }
+                    });
+                } else {
+                    unpack_rows(&interp, frame_storage, new_buffer, 8, |_, samples, output| {
+                        for (&sample, output) in samples.iter().zip(output.iter_mut()) {
+                            *output = convert(sample) as u8;
+                        }
+                    });
+                }
+            })
+        });
+
+        let new_frame =
+            Frame { storage: new_buffer, interp: output_interp, metadata: frame.metadata.clone() };
 
         Ok(Some(Payload::from(new_frame)))
     }
@@ -0,0 +1,193 @@
+use crate::{
+    nodes_cpu::calibration::{color_averages, CalibrationFile},
+    pipeline_processing::{
+        execute::ProcessingStageLockWaiter,
+        frame::{CfaDescriptor, Frame, FrameInterpretation, Raw},
+        metadata::{FrameMetadata, Levels},
+        parametrizable::{
+            ParameterType::{BoolParameter, StringParameter},
+            ParameterTypeDescriptor::Optional,
+            ParameterValue,
+            Parameterizable,
+            Parameters,
+            ParametersDescriptor,
+        },
+        payload::Payload,
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+        unpack::unpack_rows,
+    },
+};
+use anyhow::{anyhow, Context, Result};
+use std::sync::{Arc, Mutex};
+
+/// Subtracts the black level of the sensor and scales the samples so that the
+/// white level becomes the largest value of the bit depth. The levels end up
+/// in the frame metadata, where the `CinemaDngWriter` picks them up.
+pub struct BlackWhiteLevel {
+    black_level: [f64; 3],
+    /// replaces `black_level` with the average of every color in the dark frame
+    dark_frame: Option<CalibrationFile>,
+    dark_frame_black_level: Mutex<Option<DarkFrameBlackLevel>>,
+    white_level: Option<f64>,
+    normalize: bool,
+    context: ProcessingContext,
+}
+crate::register_node!(BlackWhiteLevel);
+
+// the average of every color of a dark frame, which depends on the cfa
+struct DarkFrameBlackLevel {
+    cfa: CfaDescriptor,
+    dark_frame: Arc<Vec<f32>>,
+    black: [f64; 3],
+}
+
+impl Parameterizable for BlackWhiteLevel {
+    const DESCRIPTION: Option<&'static str> = Some(
+        "subtract the black level (one value, one per color as r,g,b or from a dark frame) and scale the white level to the full range",
+    );
+
+    fn describe_parameters() -> ParametersDescriptor {
+        CalibrationFile::describe_parameters(ParametersDescriptor::new(), "dark-frame")
+            .with(
+                "black-level",
+                Optional(StringParameter, ParameterValue::StringParameter("0".to_string())),
+            )
+            .with(
+                "dark-frame",
+                Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
+            )
+            .with(
+                "white-level",
+                Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
+            )
+            .with("normalize", Optional(BoolParameter, ParameterValue::BoolParameter(true)))
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(_parameters: &Parameters, input: PortType) -> Result<PortType> { Ok(input) }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let white_level = match parameters.get::<String>("white-level")?.as_str() {
+            "" => None,
+            white_level => Some(white_level.parse().context("invalid white level")?),
+        };
+
+        Ok(Self {
+            black_level: parse_black_level(&parameters.get::<String>("black-level")?)?,
+            dark_frame: CalibrationFile::from_parameters(parameters, "dark-frame")?,
+            dark_frame_black_level: Mutex::new(None),
+            white_level,
+            normalize: parameters.get("normalize")?,
+            context,
+        })
+    }
+}
+
+impl BlackWhiteLevel {
+    fn levels(&self, interp: &Raw) -> Result<Levels> {
+        let black = match &self.dark_frame {
+            None => self.black_level,
+            Some(file) => {
+                let dark_frame = file.average(interp)?;
+                let mut cache = self.dark_frame_black_level.lock().unwrap();
+                match &*cache {
+                    Some(cached)
+                        if cached.cfa == interp.cfa
+                            && Arc::ptr_eq(&cached.dark_frame, &dark_frame) =>
+                    {
+                        cached.black
+                    }
+                    _ => {
+                        let black = color_averages(&dark_frame, interp);
+                        *cache = Some(DarkFrameBlackLevel { cfa: interp.cfa, dark_frame, black });
+                        black
+                    }
+                }
+            }
+        };
+        let white = self.white_level.unwrap_or(((1u64 << interp.bit_depth) - 1) as f64);
+        if black.iter().any(|&black| black >= white) {
+            return Err(anyhow!(
+                "the black level {:?} has to be below the white level {}",
+                black,
+                white
+            ));
+        }
+        Ok(Levels { black, white })
+    }
+}
+
+impl ProcessingNode for BlackWhiteLevel {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let frame = self.context.ensure_cpu_buffer::<Raw>(input).context("Wrong input format")?;
+        let interp = frame.interp;
+        let levels = self.levels(&interp)?;
+
+        if !self.normalize {
+            let metadata = FrameMetadata { levels: Some(levels), ..frame.metadata.clone() };
+            return Ok(Some(Payload::from(Frame {
+                interp,
+                storage: frame.storage.clone(),
+                metadata,
+            })));
+        }
+
+        let max = ((1u64 << interp.bit_depth) - 1) as f32;
+        let black = levels.black.map(|black| black as f32);
+        let scale = black.map(|black| max / (levels.white as f32 - black));
+        let width = interp.width as usize;
+        let bits_per_sample = interp.packing.bits_per_sample(interp.bit_depth);
+
+        let mut buffer = unsafe { self.context.get_uninit_cpu_buffer(interp.required_bytes()) };
+        buffer.as_mut_slice(|output| {
+            frame.storage.as_slice(|input| {
+                unpack_rows(
+                    &interp,
+                    input,
+                    output,
+                    bits_per_sample,
+                    |first_row, samples, output| {
+                        let normalized = samples.iter().enumerate().map(|(i, &sample)| {
+                            let (x, y) = (i % width, first_row + i / width);
+                            let color =
+                                interp.cfa.color_at(x as u64, y as u64).map_or(0, |c| c as usize);
+                            ((sample as f32 - black[color]) * scale[color]).round().clamp(0.0, max)
+                                as u16
+                        });
+                        interp.packing.write_samples(interp.bit_depth, normalized, output);
+                    },
+                )
+            })
+        });
+
+        let levels = Levels { black: [0.0; 3], white: max as f64 };
+        let metadata = FrameMetadata { levels: Some(levels), ..frame.metadata.clone() };
+        Ok(Some(Payload::from(Frame { interp, storage: buffer, metadata })))
+    }
+}
+
+// either one black level for all colors or r,g,b
+fn parse_black_level(black_level: &str) -> Result<[f64; 3]> {
+    let values = black_level
+        .split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid black level {}", black_level))?;
+    match values[..] {
+        [black] => Ok([black; 3]),
+        [red, green, blue] => Ok([red, green, blue]),
+        _ => Err(anyhow!(
+            "expected one black level or one for every color (r,g,b), got {}",
+            black_level
+        )),
+    }
+}
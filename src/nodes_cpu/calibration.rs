@@ -0,0 +1,128 @@
+use crate::pipeline_processing::{
+    frame::{BitPacking, CfaDescriptor, FrameInterpretation, Raw},
+    parametrizable::{
+        ParameterType::StringParameter,
+        ParameterTypeDescriptor::Optional,
+        ParameterValue,
+        Parameters,
+        ParametersDescriptor,
+    },
+    unpack::unpack,
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::File,
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+/// A file with calibration frames like dark frames, which are recorded once and
+/// then applied to every frame of a recording. The file holds one or more raw
+/// frames in the format of the frames they are applied to (e.g. recorded with
+/// `RawBlobWriter`), which are averaged to get rid of their noise. Only the
+/// packing can differ from the frames, e.g. for dark frames recorded before a
+/// `BitDepthConverter`.
+///
+/// The frames are read one after the other while they are averaged, only the
+/// average is kept in memory.
+pub struct CalibrationFile {
+    path: String,
+    packing: Option<BitPacking>,
+    average: Mutex<Option<(Raw, Arc<Vec<f32>>)>>,
+}
+
+impl CalibrationFile {
+    pub fn open(path: &str, packing: Option<BitPacking>) -> Result<Self> {
+        // the frames can only be read once the format of the input is known
+        File::open(path)
+            .with_context(|| format!("could not read the calibration file {}", path))?;
+        Ok(Self { path: path.to_string(), packing, average: Mutex::new(None) })
+    }
+
+    /// Adds the `<name>-packing` parameter for a calibration file whose path is
+    /// given by the parameter `name`
+    pub fn describe_parameters(
+        descriptor: ParametersDescriptor,
+        name: &str,
+    ) -> ParametersDescriptor {
+        descriptor.with(
+            &format!("{}-packing", name),
+            Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
+        )
+    }
+
+    /// Opens the calibration file given by the parameter `name`, if it is set
+    pub fn from_parameters(parameters: &Parameters, name: &str) -> Result<Option<Self>> {
+        let path = parameters.get::<String>(name)?;
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let packing = match parameters.get::<String>(&format!("{}-packing", name))?.as_str() {
+            "" => None,
+            packing => Some(packing.parse()?),
+        };
+        Ok(Some(Self::open(&path, packing)?))
+    }
+
+    /// The average of all frames in the file for every pixel, reading them
+    /// like frames with the interpretation `interp`. It is only computed once
+    /// for every interpretation.
+    pub fn average(&self, interp: &Raw) -> Result<Arc<Vec<f32>>> {
+        let interp = &Raw { packing: self.packing.unwrap_or(interp.packing), ..*interp };
+        let mut average = self.average.lock().unwrap();
+        match &*average {
+            Some((cached, frame)) if same_layout(cached, interp) => return Ok(frame.clone()),
+            _ => {}
+        }
+
+        let read_error = || format!("could not read the calibration file {}", self.path);
+        let mut file = File::open(&self.path).with_context(read_error)?;
+        let len = file.metadata().with_context(read_error)?.len();
+        let frame_bytes = interp.required_bytes();
+        if len == 0 || len % frame_bytes as u64 != 0 {
+            return Err(anyhow!(
+                "the calibration file {} has {} bytes, which are no whole frames of {} bytes. does it have the format of the input?",
+                self.path,
+                len,
+                frame_bytes
+            ));
+        }
+        let frames = len / frame_bytes as u64;
+        let mut sum = vec![0f64; (interp.width * interp.height) as usize];
+        let mut samples = vec![0u16; sum.len()];
+        let mut frame = vec![0u8; frame_bytes];
+        for _ in 0..frames {
+            file.read_exact(&mut frame).with_context(read_error)?;
+            unpack(interp.packing, interp.bit_depth, &frame, &mut samples);
+            for (sum, &sample) in sum.iter_mut().zip(samples.iter()) {
+                *sum += sample as f64;
+            }
+        }
+
+        let frame =
+            Arc::new(sum.into_iter().map(|sum| (sum / frames as f64) as f32).collect::<Vec<_>>());
+        *average = Some((*interp, frame.clone()));
+        Ok(frame)
+    }
+}
+
+fn same_layout(a: &Raw, b: &Raw) -> bool {
+    (a.width, a.height, a.bit_depth, a.packing) == (b.width, b.height, b.bit_depth, b.packing)
+}
+
+/// The average sample value of every color of the color filter array, indexed
+/// by `CfaColor`
+pub fn color_averages(samples: &[f32], interp: &Raw) -> [f64; 3] {
+    let mut sum = [0f64; 3];
+    let mut count = [0u64; 3];
+    for (i, &sample) in samples.iter().enumerate() {
+        let (x, y) = (i as u64 % interp.width, i as u64 / interp.width);
+        let color = interp.cfa.color_at(x, y).map_or(0, |c| c as usize);
+        sum[color] += sample as f64;
+        count[color] += 1;
+    }
+    if interp.cfa == CfaDescriptor::Mono {
+        return [sum[0] / count[0].max(1) as f64; 3];
+    }
+    [0, 1, 2].map(|color| sum[color] / count[color].max(1) as f64)
+}
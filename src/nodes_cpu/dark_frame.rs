@@ -0,0 +1,157 @@
+use crate::{
+    nodes_cpu::calibration::CalibrationFile,
+    pipeline_processing::{
+        execute::ProcessingStageLockWaiter,
+        frame::{Frame, FrameInterpretation, Raw},
+        metadata::FrameMetadata,
+        parametrizable::{
+            ParameterType::{IntRange, StringParameter},
+            ParameterTypeDescriptor::{Mandatory, Optional},
+            ParameterValue,
+            Parameterizable,
+            Parameters,
+            ParametersDescriptor,
+        },
+        payload::Payload,
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+        unpack::unpack_rows,
+    },
+};
+use anyhow::{anyhow, Context, Result};
+
+/// What `DarkFrameSubtraction` and `GpuDarkFrameSubtraction` subtract: a
+/// master dark frame, which removes the fixed pattern noise (and the black
+/// level) of every pixel.
+///
+/// With `dark-frame-exposure-time` set, the dark frame is scaled by the
+/// exposure time of the frames relative to it. The exposure time of the frames
+/// is either the `exposure-time` parameter or the `exposure-time` tag of their
+/// metadata. As all of the dark frame is scaled, this works best for dark
+/// frames that were recorded with a similar exposure time.
+pub struct DarkFrame {
+    pub file: CalibrationFile,
+    dark_frame_exposure_time: Option<f64>,
+    exposure_time: Option<f64>,
+    /// added to every sample after the subtraction, to keep the noise of black
+    /// pixels from being clipped at zero
+    pub offset: f64,
+}
+
+impl DarkFrame {
+    pub fn describe_parameters(descriptor: ParametersDescriptor) -> ParametersDescriptor {
+        CalibrationFile::describe_parameters(descriptor, "dark-frame")
+            .with("dark-frame", Mandatory(StringParameter))
+            .with(
+                "dark-frame-exposure-time",
+                Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
+            )
+            .with(
+                "exposure-time",
+                Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
+            )
+            .with("offset", Optional(IntRange(0, 65535), ParameterValue::IntRange(0)))
+    }
+
+    pub fn from_parameters(parameters: &Parameters) -> Result<Self> {
+        let exposure_time = |name| -> Result<Option<f64>> {
+            match parameters.get::<String>(name)?.as_str() {
+                "" => Ok(None),
+                time => Ok(Some(time.parse().with_context(|| format!("invalid {}", name))?)),
+            }
+        };
+        Ok(Self {
+            file: CalibrationFile::from_parameters(parameters, "dark-frame")?
+                .ok_or_else(|| anyhow!("a dark frame is required"))?,
+            dark_frame_exposure_time: exposure_time("dark-frame-exposure-time")?,
+            exposure_time: exposure_time("exposure-time")?,
+            offset: parameters.get::<i64>("offset")? as f64,
+        })
+    }
+
+    /// The factor for the dark frame before subtracting it from a frame with
+    /// this metadata
+    pub fn scale(&self, metadata: &FrameMetadata) -> Result<f32> {
+        let dark_frame_exposure_time = match self.dark_frame_exposure_time {
+            None => return Ok(1.0),
+            Some(time) => time,
+        };
+        let exposure_time = match (self.exposure_time, metadata.tags.get("exposure-time")) {
+            (Some(time), _) => time,
+            (None, Some(time)) => time.parse().context("invalid exposure-time tag")?,
+            (None, None) => {
+                return Err(anyhow!(
+                    "the exposure time of the frame is unknown. set it with the exposure-time parameter!"
+                ))
+            }
+        };
+        Ok((exposure_time / dark_frame_exposure_time) as f32)
+    }
+}
+
+pub struct DarkFrameSubtraction {
+    dark_frame: DarkFrame,
+    context: ProcessingContext,
+}
+crate::register_node!(DarkFrameSubtraction);
+impl Parameterizable for DarkFrameSubtraction {
+    const DESCRIPTION: Option<&'static str> = Some(
+        "subtract a master dark frame (the average of the frames in a calibration file) from every pixel",
+    );
+
+    fn describe_parameters() -> ParametersDescriptor {
+        DarkFrame::describe_parameters(ParametersDescriptor::new())
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(_parameters: &Parameters, input: PortType) -> Result<PortType> { Ok(input) }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self { dark_frame: DarkFrame::from_parameters(parameters)?, context })
+    }
+}
+
+impl ProcessingNode for DarkFrameSubtraction {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let frame = self.context.ensure_cpu_buffer::<Raw>(input).context("Wrong input format")?;
+        let interp = frame.interp;
+        let dark_frame = self.dark_frame.file.average(&interp)?;
+        let scale = self.dark_frame.scale(&frame.metadata)?;
+        let offset = self.dark_frame.offset as f32;
+
+        let max = ((1u64 << interp.bit_depth) - 1) as f32;
+        let width = interp.width as usize;
+        let bits_per_sample = interp.packing.bits_per_sample(interp.bit_depth);
+
+        let mut buffer = unsafe { self.context.get_uninit_cpu_buffer(interp.required_bytes()) };
+        buffer.as_mut_slice(|output| {
+            frame.storage.as_slice(|input| {
+                unpack_rows(
+                    &interp,
+                    input,
+                    output,
+                    bits_per_sample,
+                    |first_row, samples, output| {
+                        let dark_frame = &dark_frame[first_row * width..];
+                        let subtracted = samples.iter().zip(dark_frame).map(|(&sample, &dark)| {
+                            // the same rounding as in dark_frame.glsl
+                            (sample as f32 - dark * scale + offset + 0.5).floor().clamp(0.0, max)
+                                as u16
+                        });
+                        interp.packing.write_samples(interp.bit_depth, subtracted, output);
+                    },
+                )
+            })
+        });
+
+        Ok(Some(Payload::from(Frame { interp, storage: buffer, metadata: frame.metadata.clone() })))
+    }
+}
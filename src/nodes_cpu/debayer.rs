@@ -0,0 +1,346 @@
+use crate::pipeline_processing::{
+    execute::ProcessingStageLockWaiter,
+    frame::{CfaDescriptor, Raw, RgbFormat},
+    parametrizable::{
+        ParameterType::StringParameter,
+        ParameterTypeDescriptor::Optional,
+        ParameterValue,
+        Parameterizable,
+        Parameters,
+        ParametersDescriptor,
+    },
+    payload::Payload,
+    port_type::PortType,
+    processing_context::ProcessingContext,
+    processing_node::ProcessingNode,
+    unpack::unpack,
+};
+use anyhow::{anyhow, Context, Result};
+use rayon::prelude::*;
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// The ways `Debayer` and `CpuDebayer` can interpolate the missing colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemosaicAlgorithm {
+    /// averages the neighbours of each color. works with every cfa pattern.
+    Bilinear,
+    /// the gradient corrected interpolation by malvar, he and cutler
+    Malvar,
+    /// interpolates along edges, after hamilton and adams
+    HamiltonAdams,
+}
+
+impl DemosaicAlgorithm {
+    /// Checks that this algorithm can interpolate frames with the given color
+    /// filter array
+    pub fn check_cfa(self, cfa: CfaDescriptor) -> Result<()> {
+        match self {
+            DemosaicAlgorithm::Bilinear => Ok(()),
+            _ if cfa == CfaDescriptor::Mono || cfa.is_bayer() => Ok(()),
+            _ => Err(anyhow!("the {} demosaic algorithm only works with bayer patterns", self)),
+        }
+    }
+}
+
+impl FromStr for DemosaicAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bilinear" => Ok(DemosaicAlgorithm::Bilinear),
+            "malvar" => Ok(DemosaicAlgorithm::Malvar),
+            "hamilton-adams" => Ok(DemosaicAlgorithm::HamiltonAdams),
+            _ => Err(anyhow!(
+                "unknown demosaic algorithm {}, expected bilinear, malvar or hamilton-adams",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for DemosaicAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DemosaicAlgorithm::Bilinear => write!(f, "bilinear"),
+            DemosaicAlgorithm::Malvar => write!(f, "malvar"),
+            DemosaicAlgorithm::HamiltonAdams => write!(f, "hamilton-adams"),
+        }
+    }
+}
+
+/// The cpu version of `Debayer`, for machines without a gpu. It produces the
+/// same output as the `Debayer` shader (up to the rounding of float operations)
+/// and also accepts packed frames of every bit depth, which it unpacks first.
+pub struct CpuDebayer {
+    output_format: RgbFormat,
+    algorithm: DemosaicAlgorithm,
+    context: ProcessingContext,
+}
+crate::register_node!(CpuDebayer);
+impl Parameterizable for CpuDebayer {
+    const DESCRIPTION: Option<&'static str> =
+        Some("interpolate rgb frames from raw frames of any bit depth and packing on the cpu");
+
+    fn describe_parameters() -> ParametersDescriptor {
+        ParametersDescriptor::new()
+            .with(
+                "output-format",
+                Optional(StringParameter, ParameterValue::StringParameter("rgb8".to_string())),
+            )
+            .with(
+                "algorithm",
+                Optional(StringParameter, ParameterValue::StringParameter("bilinear".to_string())),
+            )
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(parameters.get::<String>("output-format")?.parse::<RgbFormat>()?.port_type())
+    }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            output_format: parameters.get::<String>("output-format")?.parse()?,
+            algorithm: parameters.get::<String>("algorithm")?.parse()?,
+            context,
+        })
+    }
+}
+
+impl ProcessingNode for CpuDebayer {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let frame = self.context.ensure_cpu_buffer::<Raw>(input).context("Wrong input format")?;
+        self.algorithm.check_cfa(frame.interp.cfa)?;
+
+        let (width, height) = (frame.interp.width, frame.interp.height);
+        let mut samples = vec![0u16; (width * height) as usize];
+        frame.storage.as_slice(|data| {
+            unpack(frame.interp.packing, frame.interp.bit_depth, data, &mut samples)
+        });
+        let raw = RawSamples {
+            samples: &samples,
+            width: width as i64,
+            height: height as i64,
+            cfa: frame.interp.cfa,
+        };
+
+        let output_format = self.output_format;
+        let bytes_per_pixel = output_format.bytes_per_pixel();
+        let max_value = output_format.max_value() as f32;
+        let scale =
+            (output_format.max_value() / ((1u64 << frame.interp.bit_depth) - 1) as f64) as f32;
+
+        let mut buffer = unsafe {
+            self.context.get_uninit_cpu_buffer((width * height) as usize * bytes_per_pixel)
+        };
+        buffer.as_mut_slice(|rgb| {
+            rgb.par_chunks_mut(width as usize * bytes_per_pixel).enumerate().for_each(
+                |(y, row)| {
+                    for (x, pixel) in row.chunks_exact_mut(bytes_per_pixel).enumerate() {
+                        let (x, y) = (x as i64, y as i64);
+                        let value = if raw.cfa == CfaDescriptor::Mono {
+                            [raw.raw_sample_at(x, y); 3]
+                        } else {
+                            match self.algorithm {
+                                DemosaicAlgorithm::Bilinear => raw.bilinear(x, y),
+                                DemosaicAlgorithm::Malvar => raw.malvar(x, y),
+                                DemosaicAlgorithm::HamiltonAdams => raw.hamilton_adams(x, y),
+                            }
+                        };
+                        let value = value.map(|value| (value * scale).clamp(0.0, max_value));
+                        write_pixel(output_format, value, pixel);
+                    }
+                },
+            );
+        });
+
+        Ok(Some(output_format.frame(
+            width,
+            height,
+            frame.interp.fps,
+            buffer,
+            frame.metadata.clone(),
+        )))
+    }
+}
+
+// writes a pixel that is already scaled and clamped to the range of the format.
+// the integer formats are truncated like the conversion in the shader
+fn write_pixel(format: RgbFormat, rgb: [f32; 3], pixel: &mut [u8]) {
+    for (channel, value) in rgb.iter().enumerate() {
+        match format {
+            RgbFormat::Rgb8 => pixel[channel] = *value as u8,
+            RgbFormat::Rgb16 => {
+                pixel[channel * 2..][..2].copy_from_slice(&(*value as u16).to_le_bytes())
+            }
+            RgbFormat::RgbF16 => {
+                pixel[channel * 2..][..2].copy_from_slice(&f16_bits(*value).to_le_bytes())
+            }
+            RgbFormat::RgbF32 => pixel[channel * 4..][..4].copy_from_slice(&value.to_le_bytes()),
+        }
+    }
+}
+
+// the half float closest to a value between 0 and 1 (ties to even, like
+// `packHalf2x16` on the gpu)
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    // the mantissa with the implicit leading one
+    let mantissa = (bits & 0x7f_ffff) | 0x80_0000;
+    if value <= 0.0 || exponent < -25 {
+        return 0;
+    }
+    // normal halfs keep 10 of the 23 mantissa bits, subnormal ones (below 2^-14)
+    // even less
+    let shift = 13 + (-14 - exponent).max(0) as u32;
+    let base = if exponent >= -14 { ((exponent + 15) as u32) << 10 } else { 0 };
+    let kept = (mantissa >> shift) & 0x3ff;
+    let rest = mantissa & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let round_up = rest > half || (rest == half && kept & 1 == 1);
+    // a carry out of the mantissa correctly increments the exponent
+    (base + kept + round_up as u32) as u16
+}
+
+// the algorithms are the same as in debayer.glsl, see there for details
+struct RawSamples<'a> {
+    samples: &'a [u16],
+    width: i64,
+    height: i64,
+    cfa: CfaDescriptor,
+}
+
+impl<'a> RawSamples<'a> {
+    // coordinates outside of the frame are mirrored at the border pixel, which
+    // keeps the color of bayer patterns
+    fn mirrored(&self, x: i64, y: i64) -> (i64, i64) {
+        let mirror = |v: i64, size: i64| {
+            let v = v.abs();
+            v.min(2 * size - 2 - v).max(0).min(size - 1)
+        };
+        (mirror(x, self.width), mirror(y, self.height))
+    }
+
+    // the coordinates have to be inside of the frame
+    fn color_at(&self, x: i64, y: i64) -> usize {
+        self.cfa.color_at(x as u64, y as u64).unwrap() as usize
+    }
+
+    // the coordinates have to be inside of the frame
+    fn raw_sample_at(&self, x: i64, y: i64) -> f32 {
+        self.samples[(y * self.width + x) as usize] as f32
+    }
+
+    fn sample_at(&self, x: i64, y: i64) -> f32 {
+        let (x, y) = self.mirrored(x, y);
+        self.raw_sample_at(x, y)
+    }
+
+    fn mirrored_color_at(&self, x: i64, y: i64) -> usize {
+        let (x, y) = self.mirrored(x, y);
+        self.color_at(x, y)
+    }
+
+    fn bilinear(&self, x: i64, y: i64) -> [f32; 3] {
+        let mut sum = [0f32; 3];
+        let mut count = [0f32; 3];
+        for ny in (y - 1).max(0)..(y + 2).min(self.height) {
+            for nx in (x - 1).max(0)..(x + 2).min(self.width) {
+                let color = self.color_at(nx, ny);
+                sum[color] += self.raw_sample_at(nx, ny);
+                count[color] += 1.0;
+            }
+        }
+
+        let mut rgb =
+            [sum[0] / count[0].max(1.0), sum[1] / count[1].max(1.0), sum[2] / count[2].max(1.0)];
+        rgb[self.color_at(x, y)] = self.raw_sample_at(x, y);
+        rgb
+    }
+
+    fn malvar(&self, x: i64, y: i64) -> [f32; 3] {
+        let s = |x, y| self.sample_at(x, y);
+        let c = s(x, y);
+        let horizontal_1 = s(x - 1, y) + s(x + 1, y);
+        let vertical_1 = s(x, y - 1) + s(x, y + 1);
+        let horizontal_2 = s(x - 2, y) + s(x + 2, y);
+        let vertical_2 = s(x, y - 2) + s(x, y + 2);
+        let diagonal = s(x - 1, y - 1) + s(x + 1, y - 1) + s(x - 1, y + 1) + s(x + 1, y + 1);
+
+        let mut rgb = [0f32; 3];
+        let own_color = self.color_at(x, y);
+        rgb[own_color] = c;
+        if own_color == 1 {
+            let horizontal_color = self.mirrored_color_at(x + 1, y);
+            rgb[horizontal_color] =
+                (5.0 * c + 4.0 * horizontal_1 - horizontal_2 - diagonal + 0.5 * vertical_2) / 8.0;
+            rgb[2 - horizontal_color] =
+                (5.0 * c + 4.0 * vertical_1 - vertical_2 - diagonal + 0.5 * horizontal_2) / 8.0;
+        } else {
+            rgb[1] =
+                (4.0 * c + 2.0 * (horizontal_1 + vertical_1) - horizontal_2 - vertical_2) / 8.0;
+            rgb[2 - own_color] =
+                (6.0 * c + 2.0 * diagonal - 1.5 * (horizontal_2 + vertical_2)) / 8.0;
+        }
+        rgb
+    }
+
+    fn green_at(&self, x: i64, y: i64) -> f32 {
+        let (x, y) = self.mirrored(x, y);
+        let s = |x, y| self.sample_at(x, y);
+        let c = s(x, y);
+        if self.color_at(x, y) == 1 {
+            return c;
+        }
+
+        let (left, right, up, down) = (s(x - 1, y), s(x + 1, y), s(x, y - 1), s(x, y + 1));
+        let horizontal_laplace = 2.0 * c - s(x - 2, y) - s(x + 2, y);
+        let vertical_laplace = 2.0 * c - s(x, y - 2) - s(x, y + 2);
+
+        let horizontal = (left + right) / 2.0 + horizontal_laplace / 4.0;
+        let vertical = (up + down) / 2.0 + vertical_laplace / 4.0;
+        let horizontal_gradient = (left - right).abs() + horizontal_laplace.abs();
+        let vertical_gradient = (up - down).abs() + vertical_laplace.abs();
+        if horizontal_gradient < vertical_gradient {
+            horizontal
+        } else if vertical_gradient < horizontal_gradient {
+            vertical
+        } else {
+            (horizontal + vertical) / 2.0
+        }
+    }
+
+    fn color_difference_at(&self, x: i64, y: i64) -> f32 {
+        self.sample_at(x, y) - self.green_at(x, y)
+    }
+
+    fn hamilton_adams(&self, x: i64, y: i64) -> [f32; 3] {
+        let d = |x, y| self.color_difference_at(x, y);
+        let g = self.green_at(x, y);
+
+        let mut rgb = [0f32; 3];
+        let own_color = self.color_at(x, y);
+        rgb[own_color] = self.raw_sample_at(x, y);
+        rgb[1] = g;
+        if own_color == 1 {
+            let horizontal_color = self.mirrored_color_at(x + 1, y);
+            rgb[horizontal_color] = g + (d(x - 1, y) + d(x + 1, y)) / 2.0;
+            rgb[2 - horizontal_color] = g + (d(x, y - 1) + d(x, y + 1)) / 2.0;
+        } else {
+            rgb[2 - own_color] =
+                g + (d(x - 1, y - 1) + d(x + 1, y - 1) + d(x - 1, y + 1) + d(x + 1, y + 1)) / 4.0;
+        }
+        rgb
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    nodes_cpu::calibration::{color_averages, CalibrationFile},
+    pipeline_processing::{
+        execute::ProcessingStageLockWaiter,
+        frame::{CfaDescriptor, Frame, FrameInterpretation, Raw},
+        parametrizable::{
+            ParameterType::StringParameter,
+            ParameterTypeDescriptor::{Mandatory, Optional},
+            ParameterValue,
+            Parameterizable,
+            Parameters,
+            ParametersDescriptor,
+        },
+        payload::Payload,
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+        unpack::unpack_rows,
+    },
+};
+use anyhow::{Context, Result};
+use std::sync::{Arc, Mutex};
+
+/// Corrects vignetting and differences in the sensitivity of the pixels by
+/// dividing every frame by a flat field: frames of an evenly lit surface,
+/// normalized so that the average of every color of the cfa is one. The frames
+/// of the flat field file are averaged and can have a dark frame subtracted.
+///
+/// If the metadata of a frame has a black level (e.g. from `BlackWhiteLevel`
+/// with `normalize=false`), only the part of the samples above it is scaled.
+pub struct FlatFieldCorrection {
+    flat_field: CalibrationFile,
+    dark_frame: Option<CalibrationFile>,
+    gains: Mutex<Option<FlatFieldGains>>,
+    context: ProcessingContext,
+}
+crate::register_node!(FlatFieldCorrection);
+
+// the factor for every pixel, computed from the averaged calibration frames
+struct FlatFieldGains {
+    cfa: CfaDescriptor,
+    flat_field: Arc<Vec<f32>>,
+    dark_frame: Option<Arc<Vec<f32>>>,
+    gains: Arc<Vec<f32>>,
+}
+
+impl Parameterizable for FlatFieldCorrection {
+    const DESCRIPTION: Option<&'static str> = Some(
+        "divide every pixel by a flat field (the average of the frames in a calibration file), normalized per color",
+    );
+
+    fn describe_parameters() -> ParametersDescriptor {
+        let descriptor =
+            CalibrationFile::describe_parameters(ParametersDescriptor::new(), "flat-field");
+        CalibrationFile::describe_parameters(descriptor, "flat-field-dark-frame")
+            .with("flat-field", Mandatory(StringParameter))
+            .with(
+                "flat-field-dark-frame",
+                Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
+            )
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(_parameters: &Parameters, input: PortType) -> Result<PortType> { Ok(input) }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            flat_field: CalibrationFile::from_parameters(parameters, "flat-field")?
+                .context("a flat field is required")?,
+            dark_frame: CalibrationFile::from_parameters(parameters, "flat-field-dark-frame")?,
+            gains: Mutex::new(None),
+            context,
+        })
+    }
+}
+
+impl FlatFieldCorrection {
+    fn gains(&self, interp: &Raw) -> Result<Arc<Vec<f32>>> {
+        let flat_field = self.flat_field.average(interp)?;
+        let dark_frame = self.dark_frame.as_ref().map(|file| file.average(interp)).transpose()?;
+
+        let mut cached = self.gains.lock().unwrap();
+        if let Some(cached) = &*cached {
+            let same_dark_frame = match (&cached.dark_frame, &dark_frame) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            if cached.cfa == interp.cfa
+                && Arc::ptr_eq(&cached.flat_field, &flat_field)
+                && same_dark_frame
+            {
+                return Ok(cached.gains.clone());
+            }
+        }
+
+        let flat = match &dark_frame {
+            Some(dark_frame) => flat_field
+                .iter()
+                .zip(dark_frame.iter())
+                .map(|(flat, dark)| (flat - dark).max(0.0))
+                .collect(),
+            None => flat_field.to_vec(),
+        };
+        let averages = color_averages(&flat, interp);
+        let gains = flat
+            .iter()
+            .enumerate()
+            .map(|(i, &flat)| {
+                let (x, y) = (i as u64 % interp.width, i as u64 / interp.width);
+                let color = interp.cfa.color_at(x, y).map_or(0, |c| c as usize);
+                // dead pixels in the flat field are left alone
+                if flat > 0.0 {
+                    (averages[color] / flat as f64) as f32
+                } else {
+                    1.0
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let gains = Arc::new(gains);
+        *cached =
+            Some(FlatFieldGains { cfa: interp.cfa, flat_field, dark_frame, gains: gains.clone() });
+        Ok(gains)
+    }
+}
+
+impl ProcessingNode for FlatFieldCorrection {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let frame = self.context.ensure_cpu_buffer::<Raw>(input).context("Wrong input format")?;
+        let interp = frame.interp;
+        let gains = self.gains(&interp)?;
+        let black =
+            frame.metadata.levels.map_or([0.0; 3], |levels| levels.black).map(|black| black as f32);
+
+        let max = ((1u64 << interp.bit_depth) - 1) as f32;
+        let width = interp.width as usize;
+        let bits_per_sample = interp.packing.bits_per_sample(interp.bit_depth);
+
+        let mut buffer = unsafe { self.context.get_uninit_cpu_buffer(interp.required_bytes()) };
+        buffer.as_mut_slice(|output| {
+            frame.storage.as_slice(|input| {
+                unpack_rows(
+                    &interp,
+                    input,
+                    output,
+                    bits_per_sample,
+                    |first_row, samples, output| {
+                        let gains = &gains[first_row * width..];
+                        let corrected =
+                            samples.iter().zip(gains).enumerate().map(|(i, (&sample, &gain))| {
+                                let (x, y) = (i % width, first_row + i / width);
+                                let color = interp
+                                    .cfa
+                                    .color_at(x as u64, y as u64)
+                                    .map_or(0, |c| c as usize);
+                                ((sample as f32 - black[color]) * gain + black[color])
+                                    .round()
+                                    .clamp(0.0, max) as u16
+                            });
+                        interp.packing.write_samples(interp.bit_depth, corrected, output);
+                    },
+                )
+            })
+        });
+
+        Ok(Some(Payload::from(Frame { interp, storage: buffer, metadata: frame.metadata.clone() })))
+    }
+}
@@ -1 +1,7 @@
 pub mod bitdepth_convert;
+pub mod black_white_level;
+pub mod calibration;
+pub mod dark_frame;
+pub mod debayer;
+pub mod flat_field;
+pub mod rgb_to_yuv;
@@ -0,0 +1,135 @@
+use crate::pipeline_processing::{
+    execute::ProcessingStageLockWaiter,
+    frame::{ChromaSubsampling, Frame, FrameInterpretation, Rgb, Yuv, YuvMatrix},
+    parametrizable::{
+        ParameterType::StringParameter,
+        ParameterTypeDescriptor::Optional,
+        ParameterValue,
+        Parameterizable,
+        Parameters,
+        ParametersDescriptor,
+    },
+    payload::Payload,
+    port_type::PortType,
+    processing_context::ProcessingContext,
+    processing_node::ProcessingNode,
+};
+use anyhow::{anyhow, Context, Result};
+use rayon::prelude::*;
+
+pub struct RgbToYuv {
+    subsampling: ChromaSubsampling,
+    matrix: YuvMatrix,
+    context: ProcessingContext,
+}
+crate::register_node!(RgbToYuv);
+impl Parameterizable for RgbToYuv {
+    const DESCRIPTION: Option<&'static str> =
+        Some("convert rgb frames to planar limited range yuv");
+
+    fn describe_parameters() -> ParametersDescriptor {
+        ParametersDescriptor::new()
+            .with(
+                "subsampling",
+                Optional(StringParameter, ParameterValue::StringParameter("420".to_string())),
+            )
+            .with(
+                "matrix",
+                Optional(StringParameter, ParameterValue::StringParameter("bt709".to_string())),
+            )
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> { Ok(vec![PortType::Rgb]) }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Yuv)
+    }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            subsampling: parameters.get::<String>("subsampling")?.parse()?,
+            matrix: parameters.get::<String>("matrix")?.parse()?,
+            context,
+        })
+    }
+}
+
+impl ProcessingNode for RgbToYuv {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let frame = self.context.ensure_cpu_buffer::<Rgb>(input).context("Wrong input format")?;
+        let interp = Yuv {
+            width: frame.interp.width,
+            height: frame.interp.height,
+            subsampling: self.subsampling,
+            matrix: self.matrix,
+            fps: frame.interp.fps,
+        };
+        // the planes are split into rows below, which needs at least one pixel
+        if interp.width == 0 || interp.height == 0 {
+            return Err(anyhow!(
+                "cannot convert an empty frame of {}x{} pixels to yuv",
+                interp.width,
+                interp.height
+            ));
+        }
+
+        let width = interp.width as usize;
+        let (chroma_width, _) = interp.chroma_size();
+        let (horizontal, vertical) = interp.subsampling.factors();
+        let (kr, kb) = interp.matrix.coefficients();
+        let kg = 1.0 - kr - kb;
+
+        let mut buffer = unsafe { self.context.get_uninit_cpu_buffer(interp.required_bytes()) };
+        buffer.as_mut_slice(|yuv| {
+            frame.storage.as_slice(|rgb| {
+                let (y_plane, chroma) = yuv.split_at_mut(interp.luma_bytes());
+                let (u_plane, v_plane) = chroma.split_at_mut(interp.chroma_bytes());
+
+                // every task gets one row of chroma samples and the rows of luma that
+                // share them
+                y_plane
+                    .par_chunks_mut(width * vertical as usize)
+                    .zip(u_plane.par_chunks_mut(chroma_width as usize))
+                    .zip(v_plane.par_chunks_mut(chroma_width as usize))
+                    .enumerate()
+                    .for_each(|(chroma_y, ((y_rows, u_row), v_row))| {
+                        for (chroma_x, (u, v)) in u_row.iter_mut().zip(v_row.iter_mut()).enumerate()
+                        {
+                            let (mut cb_sum, mut cr_sum, mut n) = (0.0, 0.0, 0.0);
+                            for dy in 0..vertical as usize {
+                                let y = chroma_y * vertical as usize + dy;
+                                let y_row = match y_rows.get_mut(dy * width..(dy + 1) * width) {
+                                    Some(row) => row,
+                                    None => break,
+                                };
+                                for dx in 0..horizontal as usize {
+                                    let x = chroma_x * horizontal as usize + dx;
+                                    if x >= width {
+                                        break;
+                                    }
+                                    let idx = (y * width + x) * 3;
+                                    let r = rgb[idx] as f32 / 255.0;
+                                    let g = rgb[idx + 1] as f32 / 255.0;
+                                    let b = rgb[idx + 2] as f32 / 255.0;
+
+                                    let luma = kr * r + kg * g + kb * b;
+                                    y_row[x] = (16.0 + 219.0 * luma).round() as u8;
+                                    cb_sum += (b - luma) / (2.0 * (1.0 - kb));
+                                    cr_sum += (r - luma) / (2.0 * (1.0 - kr));
+                                    n += 1.0;
+                                }
+                            }
+                            *u = (128.0 + 224.0 * cb_sum / n).round() as u8;
+                            *v = (128.0 + 224.0 * cr_sum / n).round() as u8;
+                        }
+                    });
+            })
+        });
+
+        Ok(Some(Payload::from(Frame { interp, storage: buffer, metadata: frame.metadata.clone() })))
+    }
+}
@@ -0,0 +1,69 @@
+#version 450
+#extension GL_EXT_shader_explicit_arithmetic_types: enable
+#extension GL_EXT_shader_explicit_arithmetic_types_int8: require
+
+// every invocation converts one sample
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+layout(push_constant) uniform PushConstantData {
+    uint width;
+    uint height;
+    uint bit_depth;
+    uint packing; // 0: msb first, 1: lsb first, 2: 16 bit little endian words
+    // 0: one byte per sample, 1: 16 bit little endian words
+    uint output_16bit;
+    // the shift from input to output samples, positive values shift left
+    int shift;
+} params;
+
+layout(set = 0, binding = 0) buffer readonly  Source { uint8_t data[]; } source;
+layout(set = 0, binding = 1) buffer writeonly Sink   { uint8_t data[]; } sink;
+
+uint source_byte(uint idx, uint len) {
+    return idx < len ? uint(source.data[idx]) : 0;
+}
+
+uint read_sample(uint idx) {
+    uint mask = (1u << params.bit_depth) - 1;
+    if (params.packing == 2) {
+        return (uint(source.data[2 * idx]) | (uint(source.data[2 * idx + 1]) << 8)) & mask;
+    }
+
+    // a sample of up to 16 bit spans at most 3 bytes
+    uint len = (params.width * params.height * params.bit_depth + 7) / 8;
+    uint bit_offset = idx * params.bit_depth;
+    uint byte_idx = bit_offset / 8;
+    uint shift = bit_offset % 8;
+    uint a = source_byte(byte_idx, len);
+    uint b = source_byte(byte_idx + 1, len);
+    uint c = source_byte(byte_idx + 2, len);
+    if (params.packing == 1) {
+        uint window = a | (b << 8) | (c << 16);
+        return (window >> shift) & mask;
+    } else {
+        uint window = (a << 16) | (b << 8) | c;
+        return (window >> (24 - shift - params.bit_depth)) & mask;
+    }
+}
+
+void main() {
+    uvec2 pos = gl_GlobalInvocationID.xy;
+    if (pos.x >= params.width || pos.y >= params.height) {
+        return;
+    }
+    uint idx = pos.y * params.width + pos.x;
+
+    uint sample_value = read_sample(idx);
+    if (params.shift >= 0) {
+        sample_value <<= uint(params.shift);
+    } else {
+        sample_value >>= uint(-params.shift);
+    }
+
+    if (params.output_16bit == 1) {
+        sink.data[2 * idx] = uint8_t(sample_value);
+        sink.data[2 * idx + 1] = uint8_t(sample_value >> 8);
+    } else {
+        sink.data[idx] = uint8_t(sample_value);
+    }
+}
@@ -1,12 +1,16 @@
-use crate::pipeline_processing::{
-    buffers::GpuBuffer,
-    execute::ProcessingStageLockWaiter,
-    frame::{Frame, Raw},
-    gpu_util::ensure_gpu_buffer,
-    parametrizable::{Parameterizable, Parameters, ParametersDescriptor},
-    payload::Payload,
-    processing_context::ProcessingContext,
-    processing_node::ProcessingNode,
+use crate::{
+    nodes_cpu::bitdepth_convert::BitDepthConversion,
+    pipeline_processing::{
+        buffers::GpuBuffer,
+        execute::ProcessingStageLockWaiter,
+        frame::{BitPacking, Frame, FrameInterpretation, Raw},
+        gpu_util::ensure_gpu_buffer,
+        parametrizable::{Parameterizable, Parameters, ParametersDescriptor},
+        payload::Payload,
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
 };
 use anyhow::{anyhow, Context, Result};
 use std::sync::Arc;
@@ -22,7 +26,7 @@ use vulkano::{
 mod compute_shader {
     vulkano_shaders::shader! {
         ty: "compute",
-        path: "src/nodes_gpu/bitdepth_convert_12_8.glsl"
+        path: "src/nodes_gpu/bitdepth_convert.glsl"
     }
 }
 
@@ -30,11 +34,25 @@ pub struct GpuBitDepthConverter {
     device: Arc<Device>,
     pipeline: Arc<ComputePipeline>,
     queue: Arc<Queue>,
+    conversion: BitDepthConversion,
 }
 
+crate::register_node!(GpuBitDepthConverter);
 impl Parameterizable for GpuBitDepthConverter {
-    fn describe_parameters() -> ParametersDescriptor { ParametersDescriptor::new() }
-    fn from_parameters(_parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    const DESCRIPTION: Option<&'static str> = Some(
+        "convert packed raw frames to 8 bit samples or to samples in 16 bit words (output=16bit) on the gpu",
+    );
+
+    fn describe_parameters() -> ParametersDescriptor {
+        BitDepthConversion::describe_parameters(ParametersDescriptor::new())
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(parameters: &Parameters, input: PortType) -> Result<PortType> {
+        Ok(BitDepthConversion::from_parameters(parameters)?.output_type(input))
+    }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
     {
@@ -47,7 +65,8 @@ impl Parameterizable for GpuBitDepthConverter {
                 .unwrap()
         });
 
-        Ok(GpuBitDepthConverter { device, pipeline, queue })
+        let conversion = BitDepthConversion::from_parameters(parameters)?;
+        Ok(GpuBitDepthConverter { device, pipeline, queue, conversion })
     }
 }
 
@@ -60,21 +79,36 @@ impl ProcessingNode for GpuBitDepthConverter {
         let (frame, fut) =
             ensure_gpu_buffer::<Raw>(input, self.queue.clone()).context("Wrong input format")?;
 
-        if frame.interp.bit_depth != 12 {
+        if !(1..=16).contains(&frame.interp.bit_depth) {
             return Err(anyhow!(
-                "A frame with bit_depth=12 is required. Convert the bit depth of the frame!"
+                "A frame with a bit depth of up to 16 bit is required, but it has {} bit",
+                frame.interp.bit_depth
             ));
         }
+        if self.conversion.is_noop(&frame.interp) {
+            return Ok(Some(input.clone()));
+        }
 
+        let output_interp = self.conversion.output_interp(frame.interp);
         let sink_buffer = DeviceLocalBuffer::<[u8]>::array(
             self.device.clone(),
-            frame.interp.width * frame.interp.height,
-            BufferUsage { storage_buffer: true, ..BufferUsage::none() },
+            output_interp.required_bytes() as u64,
+            BufferUsage { storage_buffer: true, transfer_source: true, ..BufferUsage::none() },
             std::iter::once(self.queue.family()),
         )?;
 
-        let push_constants =
-            compute_shader::ty::PushConstantData { width: frame.interp.width as u32 };
+        let push_constants = compute_shader::ty::PushConstantData {
+            width: frame.interp.width as u32,
+            height: frame.interp.height as u32,
+            bit_depth: frame.interp.bit_depth as u32,
+            packing: match frame.interp.packing {
+                BitPacking::MsbFirst => 0,
+                BitPacking::LsbFirst => 1,
+                BitPacking::U16Le => 2,
+            },
+            output_16bit: (output_interp.packing == BitPacking::U16Le) as u32,
+            shift: self.conversion.shift(frame.interp.bit_depth) as i32,
+        };
 
         let layout = self.pipeline.layout().descriptor_set_layouts()[0].clone();
         let set = Arc::new({
@@ -99,7 +133,11 @@ impl ProcessingNode for GpuBitDepthConverter {
             )
             .push_constants(self.pipeline.layout().clone(), 0, push_constants)
             .bind_pipeline_compute(self.pipeline.clone())
-            .dispatch([frame.interp.width as u32 / 16 / 2, frame.interp.height as u32 / 32, 1])?;
+            .dispatch([
+                (frame.interp.width as u32 + 31) / 32,
+                (frame.interp.height as u32 + 31) / 32,
+                1,
+            ])?;
         let command_buffer = builder.build()?;
 
         let future =
@@ -107,8 +145,9 @@ impl ProcessingNode for GpuBitDepthConverter {
 
         future.wait(None).unwrap();
         Ok(Some(Payload::from(Frame {
-            interp: Raw { bit_depth: 8, ..frame.interp },
+            interp: output_interp,
             storage: GpuBuffer::from(sink_buffer),
+            metadata: frame.metadata.clone(),
         })))
     }
 }
@@ -0,0 +1,49 @@
+#version 450
+#extension GL_EXT_shader_explicit_arithmetic_types: enable
+#extension GL_EXT_shader_explicit_arithmetic_types_int8: require
+
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+layout(push_constant) uniform PushConstantData {
+    uint width;
+    uint height;
+    // whether the samples are 16 bit little endian words instead of bytes
+    uint input_16bit;
+    // the largest value of the bit depth, where the results are clipped
+    float max_value;
+    // the factor for the dark frame (the ratio of the exposure times)
+    float scale;
+    // added to every result, so that noise below the dark frame is kept
+    float offset;
+} params;
+
+layout(set = 0, binding = 0) buffer readonly Source { uint8_t data[]; } source;
+layout(set = 0, binding = 1) buffer readonly DarkFrame { float data[]; } dark_frame;
+layout(set = 0, binding = 2) buffer writeonly Sink { uint8_t data[]; } sink;
+
+void main() {
+    uvec2 pos = gl_GlobalInvocationID.xy;
+    if (pos.x >= params.width || pos.y >= params.height) {
+        return;
+    }
+    uint idx = pos.y * params.width + pos.x;
+
+    float value;
+    if (params.input_16bit == 1) {
+        value = float(uint(source.data[2 * idx]) | (uint(source.data[2 * idx + 1]) << 8));
+    } else {
+        value = float(source.data[idx]);
+    }
+
+    // rounds half up like DarkFrameSubtraction, which the dark frame fixtures
+    // in tests/calibration.rs check
+    value = clamp(floor(value - dark_frame.data[idx] * params.scale + params.offset + 0.5), 0., params.max_value);
+
+    uint result = uint(value);
+    if (params.input_16bit == 1) {
+        sink.data[2 * idx] = uint8_t(result);
+        sink.data[2 * idx + 1] = uint8_t(result >> 8);
+    } else {
+        sink.data[idx] = uint8_t(result);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    nodes_cpu::dark_frame::DarkFrame,
+    pipeline_processing::{
+        buffers::GpuBuffer,
+        execute::ProcessingStageLockWaiter,
+        frame::{BitPacking, Frame, FrameInterpretation, Raw},
+        gpu_util::ensure_gpu_buffer,
+        parametrizable::{Parameterizable, Parameters, ParametersDescriptor},
+        payload::Payload,
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
+};
+use anyhow::{anyhow, Context, Result};
+use std::sync::{Arc, Mutex};
+use vulkano::{
+    buffer::{BufferUsage, DeviceLocalBuffer, ImmutableBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage::OneTimeSubmit},
+    descriptor_set::persistent::PersistentDescriptorSet,
+    device::{Device, Queue},
+    pipeline::{ComputePipeline, PipelineBindPoint},
+    sync::GpuFuture,
+};
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/nodes_gpu/dark_frame.glsl"
+    }
+}
+
+/// The gpu version of `DarkFrameSubtraction`. It needs frames with 8 bit
+/// samples or samples in 16 bit words, e.g. from a `GpuBitDepthConverter`.
+pub struct GpuDarkFrameSubtraction {
+    device: Arc<Device>,
+    pipeline: Arc<ComputePipeline>,
+    queue: Arc<Queue>,
+    dark_frame: DarkFrame,
+    // the averaged dark frame and its copy on the gpu
+    uploaded: Mutex<Option<(Arc<Vec<f32>>, Arc<ImmutableBuffer<[f32]>>)>>,
+}
+
+crate::register_node!(GpuDarkFrameSubtraction);
+impl Parameterizable for GpuDarkFrameSubtraction {
+    const DESCRIPTION: Option<&'static str> = Some(
+        "subtract a master dark frame (the average of the frames in a calibration file) from every pixel on the gpu",
+    );
+
+    fn describe_parameters() -> ParametersDescriptor {
+        DarkFrame::describe_parameters(ParametersDescriptor::new())
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(_parameters: &Parameters, input: PortType) -> Result<PortType> { Ok(input) }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let (device, queues) = context.require_vulkan()?;
+        let queue = queues.iter().find(|&q| q.family().supports_compute()).unwrap().clone();
+
+        let pipeline = Arc::new({
+            let shader = compute_shader::Shader::load(device.clone()).unwrap();
+            ComputePipeline::new(device.clone(), &shader.main_entry_point(), &(), None, |_| {})
+                .unwrap()
+        });
+
+        Ok(GpuDarkFrameSubtraction {
+            device,
+            pipeline,
+            queue,
+            dark_frame: DarkFrame::from_parameters(parameters)?,
+            uploaded: Mutex::new(None),
+        })
+    }
+}
+
+impl GpuDarkFrameSubtraction {
+    fn dark_frame_buffer(&self, interp: &Raw) -> Result<Arc<ImmutableBuffer<[f32]>>> {
+        let dark_frame = self.dark_frame.file.average(interp)?;
+        let mut uploaded = self.uploaded.lock().unwrap();
+        if let Some((frame, buffer)) = &*uploaded {
+            if Arc::ptr_eq(frame, &dark_frame) {
+                return Ok(buffer.clone());
+            }
+        }
+
+        let (buffer, future) = ImmutableBuffer::from_iter(
+            dark_frame.iter().copied(),
+            BufferUsage::storage_buffer(),
+            self.queue.clone(),
+        )?;
+        future.then_signal_fence_and_flush()?.wait(None)?;
+        *uploaded = Some((dark_frame, buffer.clone()));
+        Ok(buffer)
+    }
+}
+
+impl ProcessingNode for GpuDarkFrameSubtraction {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let (frame, fut) =
+            ensure_gpu_buffer::<Raw>(input, self.queue.clone()).context("Wrong input format")?;
+
+        let input_16bit = frame.interp.packing == BitPacking::U16Le;
+        if !frame.interp.is_8bit() && !input_16bit {
+            return Err(anyhow!(
+                "A frame with 8 bit samples or 16 bit words is required. Convert the bit depth of the frame!"
+            ));
+        }
+        let dark_frame = self.dark_frame_buffer(&frame.interp)?;
+
+        let sink_buffer = DeviceLocalBuffer::<[u8]>::array(
+            self.device.clone(),
+            frame.interp.required_bytes() as u64,
+            BufferUsage { storage_buffer: true, transfer_source: true, ..BufferUsage::none() },
+            std::iter::once(self.queue.family()),
+        )?;
+
+        let push_constants = compute_shader::ty::PushConstantData {
+            width: frame.interp.width as u32,
+            height: frame.interp.height as u32,
+            input_16bit: input_16bit as u32,
+            max_value: ((1u64 << frame.interp.bit_depth) - 1) as f32,
+            scale: self.dark_frame.scale(&frame.metadata)?,
+            offset: self.dark_frame.offset as f32,
+        };
+
+        let layout = self.pipeline.layout().descriptor_set_layouts()[0].clone();
+        let set = Arc::new({
+            let mut builder = PersistentDescriptorSet::start(layout);
+            builder.add_buffer(frame.storage.untyped())?;
+            builder.add_buffer(dark_frame)?;
+            builder.add_buffer(sink_buffer.clone())?;
+            builder.build()?
+        });
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.device.clone(),
+            self.queue.family(),
+            OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .bind_pipeline_compute(self.pipeline.clone())
+            .dispatch([
+                (frame.interp.width as u32 + 31) / 32,
+                (frame.interp.height as u32 + 31) / 32,
+                1,
+            ])?;
+        let command_buffer = builder.build()?;
+
+        let future =
+            fut.then_execute(self.queue.clone(), command_buffer)?.then_signal_fence_and_flush()?;
+
+        future.wait(None).unwrap();
+        Ok(Some(Payload::from(Frame {
+            interp: frame.interp,
+            storage: GpuBuffer::from(sink_buffer),
+            metadata: frame.metadata.clone(),
+        })))
+    }
+}
@@ -8,69 +8,210 @@ layout(push_constant) uniform PushConstantData {
     uint width;
     uint height;
 
-// these are actual coordinates of the first red pixel (unlike everywhere else)
-    uint first_red_x;
-    uint first_red_y;
+    // the repeating color filter pattern. 0x0 means a mono sensor. the colors
+    // are packed row by row with 2 bits each (R=0, G=1, B=2)
+    uint cfa_width;
+    uint cfa_height;
+    uint cfa_pattern[4];
+
+    // whether the source has a 16 bit little endian word per sample instead of a byte
+    uint input_16bit;
+    // the factor from source values to output values
+    float scale;
+    // 0: rgb 8 bit, 1: rgb 16 bit, 2: rgb half float, 3: rgb f32
+    uint output_format;
+    // 0: bilinear, 1: malvar-he-cutler, 2: hamilton-adams. everything but bilinear
+    // needs a bayer pattern
+    uint algorithm;
 } params;
 
 layout(set = 0, binding = 0) buffer readonly Source { uint8_t data[]; } source;
 layout(set = 0, binding = 1) buffer writeonly Sink   { uint8_t data[]; } sink;
 
+// mirrors coordinates outside of the frame at the border pixel. this keeps the
+// color of a bayer pattern, as it moves by an even number of pixels.
+ivec2 mirrored(int x, int y) {
+    ivec2 size = ivec2(params.width, params.height);
+    ivec2 pos = abs(ivec2(x, y));
+    pos = min(pos, 2 * size - 2 - pos);
+    return clamp(pos, ivec2(0), size - 1);
+}
+
+// the coordinates have to be inside of the frame
+uint color_at(int x, int y) {
+    uint idx = uint(y % int(params.cfa_height)) * params.cfa_width + uint(x % int(params.cfa_width));
+    return (params.cfa_pattern[idx / 16] >> ((idx % 16) * 2)) & 3;
+}
+
+// the coordinates have to be inside of the frame
+float raw_sample_at(int x, int y) {
+    int idx = x + y * int(params.width);
+    if (params.input_16bit == 1) {
+        return float(uint(source.data[2 * idx]) | (uint(source.data[2 * idx + 1]) << 8));
+    } else {
+        return float(source.data[idx]);
+    }
+}
+
+float sample_at(int x, int y) {
+    ivec2 pos = mirrored(x, y);
+    return raw_sample_at(pos.x, pos.y);
+}
+
+uint mirrored_color_at(int x, int y) {
+    ivec2 pos = mirrored(x, y);
+    return color_at(pos.x, pos.y);
+}
+
+// every color that is not measured at this pixel is the average of the pixels
+// with that color in the 3x3 neighbourhood. for bayer patterns this averages
+// the 2 or 4 direct neighbours. neighbours outside of the frame are left out.
+vec3 bilinear(int x, int y) {
+    vec3 sum = vec3(0.);
+    vec3 count = vec3(0.);
+    for (int dy = -1; dy <= 1; dy++) {
+        for (int dx = -1; dx <= 1; dx++) {
+            int nx = x + dx;
+            int ny = y + dy;
+            if (nx < 0 || ny < 0 || nx >= int(params.width) || ny >= int(params.height)) {
+                continue;
+            }
+            uint color = color_at(nx, ny);
+            sum[color] += raw_sample_at(nx, ny);
+            count[color] += 1.;
+        }
+    }
+    vec3 rgb = sum / max(count, vec3(1.));
+    rgb[color_at(x, y)] = raw_sample_at(x, y);
+    return rgb;
+}
+
+// the gradient corrected linear interpolation from "High-quality linear
+// interpolation for demosaicing of bayer-patterned color images" by malvar, he
+// and cutler
+vec3 malvar(int x, int y) {
+    float c = sample_at(x, y);
+    float horizontal_1 = sample_at(x - 1, y) + sample_at(x + 1, y);
+    float vertical_1 = sample_at(x, y - 1) + sample_at(x, y + 1);
+    float horizontal_2 = sample_at(x - 2, y) + sample_at(x + 2, y);
+    float vertical_2 = sample_at(x, y - 2) + sample_at(x, y + 2);
+    float diagonal = sample_at(x - 1, y - 1) + sample_at(x + 1, y - 1)
+        + sample_at(x - 1, y + 1) + sample_at(x + 1, y + 1);
+
+    vec3 rgb;
+    uint own_color = color_at(x, y);
+    rgb[own_color] = c;
+    if (own_color == 1) {
+        // the colors of the horizontal and the vertical neighbours
+        uint horizontal_color = mirrored_color_at(x + 1, y);
+        rgb[horizontal_color] =
+            (5. * c + 4. * horizontal_1 - horizontal_2 - diagonal + 0.5 * vertical_2) / 8.;
+        rgb[2 - horizontal_color] =
+            (5. * c + 4. * vertical_1 - vertical_2 - diagonal + 0.5 * horizontal_2) / 8.;
+    } else {
+        rgb[1] = (4. * c + 2. * (horizontal_1 + vertical_1) - horizontal_2 - vertical_2) / 8.;
+        rgb[2 - own_color] = (6. * c + 2. * diagonal - 1.5 * (horizontal_2 + vertical_2)) / 8.;
+    }
+    return rgb;
+}
+
+// green interpolated along the direction with the smaller gradient, as in
+// hamilton and adams "Adaptive color plane interpolation in single sensor color
+// electronic camera"
+float green_at(int x, int y) {
+    ivec2 pos = mirrored(x, y);
+    x = pos.x;
+    y = pos.y;
+    float c = sample_at(x, y);
+    if (color_at(x, y) == 1) {
+        return c;
+    }
+
+    float left = sample_at(x - 1, y);
+    float right = sample_at(x + 1, y);
+    float up = sample_at(x, y - 1);
+    float down = sample_at(x, y + 1);
+    float horizontal_laplace = 2. * c - sample_at(x - 2, y) - sample_at(x + 2, y);
+    float vertical_laplace = 2. * c - sample_at(x, y - 2) - sample_at(x, y + 2);
+
+    float horizontal = (left + right) / 2. + horizontal_laplace / 4.;
+    float vertical = (up + down) / 2. + vertical_laplace / 4.;
+    float horizontal_gradient = abs(left - right) + abs(horizontal_laplace);
+    float vertical_gradient = abs(up - down) + abs(vertical_laplace);
+    if (horizontal_gradient < vertical_gradient) {
+        return horizontal;
+    } else if (vertical_gradient < horizontal_gradient) {
+        return vertical;
+    } else {
+        return (horizontal + vertical) / 2.;
+    }
+}
+
+// red and blue are interpolated as the difference to green, which is smooth
+// even across edges
+float color_difference_at(int x, int y) {
+    return sample_at(x, y) - green_at(x, y);
+}
+
+vec3 hamilton_adams(int x, int y) {
+    float g = green_at(x, y);
+
+    vec3 rgb;
+    uint own_color = color_at(x, y);
+    rgb[own_color] = sample_at(x, y);
+    rgb[1] = g;
+    if (own_color == 1) {
+        uint horizontal_color = mirrored_color_at(x + 1, y);
+        rgb[horizontal_color] =
+            g + (color_difference_at(x - 1, y) + color_difference_at(x + 1, y)) / 2.;
+        rgb[2 - horizontal_color] =
+            g + (color_difference_at(x, y - 1) + color_difference_at(x, y + 1)) / 2.;
+    } else {
+        rgb[2 - own_color] = g + (
+            color_difference_at(x - 1, y - 1) + color_difference_at(x + 1, y - 1)
+            + color_difference_at(x - 1, y + 1) + color_difference_at(x + 1, y + 1)
+        ) / 4.;
+    }
+    return rgb;
+}
+
+void write_bytes(uint idx, uint value, uint count) {
+    for (uint i = 0; i < count; i++) {
+        sink.data[idx + i] = uint8_t(value >> (8 * i));
+    }
+}
+
 void main() {
     uvec2 pos = gl_GlobalInvocationID.xy;
+    if (pos.x >= params.width || pos.y >= params.height) {
+        return;
+    }
+    int x = int(pos.x);
+    int y = int(pos.y);
 
-    /*
-    variables a-i are the neighbour pixels (we are e)
-    a b c
-    d e f
-    g h i
-    */
-
-    float a = float(source.data[(pos.x - 1) + (pos.y - 1) * params.width]);
-    float b = float(source.data[(pos.x    ) + (pos.y - 1) * params.width]);
-    float c = float(source.data[(pos.x + 1) + (pos.y - 1) * params.width]);
-    float d = float(source.data[(pos.x - 1) + (pos.y    ) * params.width]);
-    float e = float(source.data[(pos.x    ) + (pos.y    ) * params.width]);
-    float f = float(source.data[(pos.x + 1) + (pos.y    ) * params.width]);
-    float g = float(source.data[(pos.x - 1) + (pos.y + 1) * params.width]);
-    float h = float(source.data[(pos.x    ) + (pos.y + 1) * params.width]);
-    float i = float(source.data[(pos.x + 1) + (pos.y + 1) * params.width]);
-
-    vec3 red_pixel = vec3(
-        e,
-        (f + d + h + b) / 4.,
-        (i + a + g + c) / 4.
-    );
-    vec3 blue_pixel = vec3(
-        (i + a + g + c) / 4.,
-        (f + d + h + b) / 4.,
-        e
-    );
-    vec3 green_pixel_red_row = vec3(
-        (d + f) / 2.,
-        e,
-        (b + h) / 2.
-    );
-    vec3 green_pixel_blue_row = vec3(
-        (b + h) / 2.,
-        e,
-        (d + f) / 2.
-    );
-
-    float x_red = float((pos.x + params.first_red_x + 1) % 2);
-    float x_red_not = float((pos.x + params.first_red_x) % 2);
-    float y_red = float((pos.y + params.first_red_y + 1) % 2);
-    float y_red_not = float((pos.y + params.first_red_y) % 2);
-
-    vec3 rgb = (
-        + red_pixel * x_red * y_red
-        + blue_pixel * x_red_not * y_red_not
-        + green_pixel_red_row * x_red_not * y_red
-        + green_pixel_blue_row * x_red * y_red_not
-    );
-
-    sink.data[(pos.y * params.width + pos.x) * 3 + 0] = uint8_t(rgb.r);
-    sink.data[(pos.y * params.width + pos.x) * 3 + 1] = uint8_t(rgb.g);
-    sink.data[(pos.y * params.width + pos.x) * 3 + 2] = uint8_t(rgb.b);
-}
+    vec3 rgb;
+    if (params.cfa_width == 0) {
+        rgb = vec3(raw_sample_at(x, y));
+    } else if (params.algorithm == 1) {
+        rgb = malvar(x, y);
+    } else if (params.algorithm == 2) {
+        rgb = hamilton_adams(x, y);
+    } else {
+        rgb = bilinear(x, y);
+    }
 
+    float max_value = params.output_format == 0 ? 255. : params.output_format == 1 ? 65535. : 1.;
+    rgb = clamp(rgb * params.scale, 0., max_value);
+    uint pixel = pos.y * params.width + pos.x;
+    for (uint channel = 0; channel < 3; channel++) {
+        if (params.output_format == 0) {
+            sink.data[pixel * 3 + channel] = uint8_t(rgb[channel]);
+        } else if (params.output_format == 1) {
+            write_bytes((pixel * 3 + channel) * 2, uint(rgb[channel]), 2);
+        } else if (params.output_format == 2) {
+            write_bytes((pixel * 3 + channel) * 2, packHalf2x16(vec2(rgb[channel], 0.)), 2);
+        } else {
+            write_bytes((pixel * 3 + channel) * 4, floatBitsToUint(rgb[channel]), 4);
+        }
+    }
+}
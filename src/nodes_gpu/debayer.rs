@@ -1,12 +1,23 @@
-use crate::pipeline_processing::{
-    buffers::GpuBuffer,
-    execute::ProcessingStageLockWaiter,
-    frame::{Frame, Raw, Rgb},
-    gpu_util::ensure_gpu_buffer,
-    parametrizable::{Parameterizable, Parameters, ParametersDescriptor},
-    payload::Payload,
-    processing_context::ProcessingContext,
-    processing_node::ProcessingNode,
+use crate::{
+    nodes_cpu::debayer::DemosaicAlgorithm,
+    pipeline_processing::{
+        buffers::GpuBuffer,
+        execute::ProcessingStageLockWaiter,
+        frame::{BitPacking, CfaDescriptor, Raw, RgbFormat},
+        gpu_util::ensure_gpu_buffer,
+        parametrizable::{
+            ParameterType::StringParameter,
+            ParameterTypeDescriptor::Optional,
+            ParameterValue,
+            Parameterizable,
+            Parameters,
+            ParametersDescriptor,
+        },
+        payload::Payload,
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
 };
 use anyhow::{anyhow, Context, Result};
 use std::sync::Arc;
@@ -30,11 +41,34 @@ pub struct Debayer {
     device: Arc<Device>,
     pipeline: Arc<ComputePipeline>,
     queue: Arc<Queue>,
+    output_format: RgbFormat,
+    algorithm: DemosaicAlgorithm,
 }
 
+crate::register_node!(Debayer);
 impl Parameterizable for Debayer {
-    fn describe_parameters() -> ParametersDescriptor { ParametersDescriptor::default() }
-    fn from_parameters(_parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    const DESCRIPTION: Option<&'static str> = Some(
+        "interpolate rgb frames from raw frames with 8 bit samples or samples in 16 bit words",
+    );
+
+    fn describe_parameters() -> ParametersDescriptor {
+        ParametersDescriptor::default()
+            .with(
+                "output-format",
+                Optional(StringParameter, ParameterValue::StringParameter("rgb8".to_string())),
+            )
+            .with(
+                "algorithm",
+                Optional(StringParameter, ParameterValue::StringParameter("bilinear".to_string())),
+            )
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(parameters.get::<String>("output-format")?.parse::<RgbFormat>()?.port_type())
+    }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
     {
@@ -47,7 +81,9 @@ impl Parameterizable for Debayer {
                 .unwrap()
         });
 
-        Ok(Debayer { device, pipeline, queue })
+        let output_format = parameters.get::<String>("output-format")?.parse()?;
+        let algorithm = parameters.get::<String>("algorithm")?.parse()?;
+        Ok(Debayer { device, pipeline, queue, output_format, algorithm })
     }
 }
 
@@ -60,29 +96,62 @@ impl ProcessingNode for Debayer {
         let (frame, fut) =
             ensure_gpu_buffer::<Raw>(input, self.queue.clone()).context("Wrong input format")?;
 
-        if frame.interp.bit_depth != 8 {
+        let input_16bit = frame.interp.packing == BitPacking::U16Le;
+        if !frame.interp.is_8bit() && !input_16bit {
             return Err(anyhow!(
-                "A frame with bit_depth=8 is required. Convert the bit depth of the frame!"
+                "A frame with 8 bit samples or 16 bit words is required. Convert the bit depth of the frame!"
             ));
         }
+        self.algorithm.check_cfa(frame.interp.cfa)?;
 
         let sink_buffer = DeviceLocalBuffer::<[u8]>::array(
             self.device.clone(),
-            frame.interp.width * frame.interp.height * 3,
+            frame.interp.width * frame.interp.height * self.output_format.bytes_per_pixel() as u64,
             BufferUsage {
                 storage_buffer: true,
                 storage_texel_buffer: true,
+                uniform_texel_buffer: true,
                 transfer_source: true,
                 ..BufferUsage::none()
             },
             std::iter::once(self.queue.family()),
         )?;
 
+        let (cfa_width, cfa_height, cfa_pattern) = match frame.interp.cfa {
+            CfaDescriptor::Mono => (0, 0, [0; 4]),
+            cfa @ CfaDescriptor::Pattern { .. } => {
+                let (width, height) = cfa.size();
+                let mut pattern = [0u32; 4];
+                for y in 0..height {
+                    for x in 0..width {
+                        let idx = y * width + x;
+                        pattern[idx as usize / 16] |=
+                            (cfa.color_at(x, y).unwrap() as u32) << ((idx % 16) * 2);
+                    }
+                }
+                (width as u32, height as u32, pattern)
+            }
+        };
         let push_constants = compute_shader::ty::PushConstantData {
             width: frame.interp.width as u32,
             height: frame.interp.height as u32,
-            first_red_x: (!frame.interp.cfa.first_is_red_x) as u32,
-            first_red_y: (!frame.interp.cfa.first_is_red_y) as u32,
+            cfa_width,
+            cfa_height,
+            cfa_pattern,
+            input_16bit: input_16bit as u32,
+            scale: (self.output_format.max_value() / ((1u64 << frame.interp.bit_depth) - 1) as f64)
+                as f32,
+            output_format: match self.output_format {
+                RgbFormat::Rgb8 => 0,
+                RgbFormat::Rgb16 => 1,
+                RgbFormat::RgbF16 => 2,
+                RgbFormat::RgbF32 => 3,
+            },
+            algorithm: match self.algorithm {
+                DemosaicAlgorithm::Bilinear => 0,
+                DemosaicAlgorithm::Malvar => 1,
+                DemosaicAlgorithm::HamiltonAdams => 2,
+            },
         };
 
         let layout = self.pipeline.layout().descriptor_set_layouts()[0].clone();
@@ -108,20 +177,23 @@ impl ProcessingNode for Debayer {
             )
             .push_constants(self.pipeline.layout().clone(), 0, push_constants)
             .bind_pipeline_compute(self.pipeline.clone())
-            .dispatch([frame.interp.width as u32 / 32, frame.interp.height as u32 / 32, 1])?;
+            .dispatch([
+                (frame.interp.width as u32 + 31) / 32,
+                (frame.interp.height as u32 + 31) / 32,
+                1,
+            ])?;
         let command_buffer = builder.build()?;
 
         let future =
             fut.then_execute(self.queue.clone(), command_buffer)?.then_signal_fence_and_flush()?;
 
         future.wait(None).unwrap();
-        Ok(Some(Payload::from(Frame {
-            interp: Rgb {
-                width: frame.interp.width,
-                height: frame.interp.height,
-                fps: frame.interp.fps,
-            },
-            storage: GpuBuffer::from(sink_buffer),
-        })))
+        Ok(Some(self.output_format.frame(
+            frame.interp.width,
+            frame.interp.height,
+            frame.interp.fps,
+            GpuBuffer::from(sink_buffer),
+            frame.metadata.clone(),
+        )))
     }
 }
@@ -9,9 +9,10 @@ use crate::pipeline_processing::{
         ParametersDescriptor,
     },
     payload::Payload,
+    port_type::PortType,
     processing_node::ProcessingNode,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use std::{
     sync::{
         mpsc::{
@@ -24,7 +25,7 @@ use std::{
     },
     thread,
     thread::JoinHandle,
-    time::{Duration, Instant},
+    time::Instant,
 };
 use vulkano::{
     buffer::BufferView,
@@ -34,7 +35,11 @@ use vulkano::{
         SubpassContents,
     },
     descriptor_set::PersistentDescriptorSet,
-    format::Format::R8_UNORM,
+    device::Queue,
+    format::{
+        Format,
+        Format::{R16_SFLOAT, R16_UNORM, R32_SFLOAT, R8_UNORM},
+    },
     image::{view::ImageView, ImageAccess, ImageUsage, SwapchainImage},
     pipeline::{viewport::Viewport, GraphicsPipeline, PipelineBindPoint},
     render_pass::{Framebuffer, FramebufferAbstract, RenderPass, Subpass},
@@ -45,7 +50,8 @@ use vulkano::{
 };
 
 use crate::pipeline_processing::{
-    frame::Rgb,
+    buffers::GpuBuffer,
+    frame::{FrameRate, Rgb, Rgb16, RgbF16, RgbF32},
     gpu_util::ensure_gpu_buffer,
     processing_context::ProcessingContext,
 };
@@ -109,13 +115,14 @@ mod fragment_shader {
             layout(location = 0) in vec2 tex_coords;
             layout(location = 0) out vec4 f_color;
 
-            layout(set = 0, binding = 0, r8) uniform readonly imageBuffer buf;
+            // the format of the buffer view decides the sample type
+            layout(set = 0, binding = 0) uniform samplerBuffer buf;
 
             vec3 get_px(int x, int y) {
                 return vec3(
-                    imageLoad(buf, y * int(params.width) * 3 + x * 3 + 0).r,
-                    imageLoad(buf, y * int(params.width) * 3 + x * 3 + 1).r,
-                    imageLoad(buf, y * int(params.width) * 3 + x * 3 + 2).r
+                    texelFetch(buf, y * int(params.width) * 3 + x * 3 + 0).r,
+                    texelFetch(buf, y * int(params.width) * 3 + x * 3 + 1).r,
+                    texelFetch(buf, y * int(params.width) * 3 + x * 3 + 2).r
                 );
             }
 
@@ -131,9 +138,10 @@ mod fragment_shader {
 
 pub struct Display {
     tx: Mutex<SyncSender<Option<Payload>>>,
-    join_handle: Option<JoinHandle<()>>,
+    join_handle: Mutex<Option<JoinHandle<()>>>,
     blocking: bool,
 }
+crate::register_node!(Display);
 impl Parameterizable for Display {
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::default()
@@ -142,6 +150,12 @@ impl Parameterizable for Display {
             .with("live", Optional(BoolParameter, ParameterValue::BoolParameter(false)))
     }
 
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::Rgb, PortType::Rgb16, PortType::RgbF16, PortType::RgbF32])
+    }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Empty)
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
@@ -221,7 +235,8 @@ impl Parameterizable for Display {
             let mut recreate_swapchain = false;
             let mut previous_frame_end = Some(sync::now(device.clone()).boxed());
             let mut next_frame_time = Instant::now();
-            let mut source_buffer = None;
+            let mut source_buffer: Option<GpuBuffer> = None;
+            let mut source_format = R8_UNORM;
             let mut source_future = None;
             let mut frame_width = 1u32;
             let mut frame_height = 1u32;
@@ -259,15 +274,16 @@ impl Parameterizable for Display {
                             Err(_) => {}
                             Ok(None) => *control_flow = ControlFlow::Exit,
                             Ok(Some(ref mut frame)) => {
-                                let (frame, fut) = ensure_gpu_buffer::<Rgb>(frame, queue.clone())
+                                let frame = DisplayFrame::from_payload(frame, queue.clone())
                                     .context("Wrong input format")
                                     .unwrap();
-                                frame_width = frame.interp.width as _;
-                                frame_height = frame.interp.height as _;
+                                frame_width = frame.width as _;
+                                frame_height = frame.height as _;
 
-                                next_frame_time += Duration::from_secs_f64(1.0 / frame.interp.fps);
-                                source_buffer = Some(frame);
-                                source_future = Some(fut);
+                                next_frame_time += frame.fps.frame_duration();
+                                source_buffer = Some(frame.storage);
+                                source_format = frame.format;
+                                source_future = Some(frame.future);
                             }
                         }
                     }
@@ -294,11 +310,8 @@ impl Parameterizable for Display {
                     let set = Arc::new({
                         let mut set = PersistentDescriptorSet::start(layout);
                         set.add_buffer_view(Arc::new(
-                            BufferView::new(
-                                source_buffer.as_ref().unwrap().storage.typed(),
-                                R8_UNORM,
-                            )
-                            .unwrap(),
+                            BufferView::new(source_buffer.as_ref().unwrap().typed(), source_format)
+                                .unwrap(),
                         ))
                         .unwrap();
                         set.build().unwrap()
@@ -373,7 +386,7 @@ impl Parameterizable for Display {
 
         Ok(Self {
             tx: Mutex::new(tx),
-            join_handle: Some(join_handle),
+            join_handle: Mutex::new(Some(join_handle)),
             blocking: parameters.get("blocking")?,
         })
     }
@@ -403,11 +416,53 @@ impl ProcessingNode for Display {
                 })
         }
     }
+
+    fn finish(&self) -> Result<()> {
+        // if the window was closed already, the display thread is gone and cant receive
+        // this
+        let _ = self.tx.lock().unwrap().send(None);
+        match self.join_handle.lock().unwrap().take() {
+            Some(join_handle) => {
+                join_handle.join().map_err(|_| anyhow!("the display thread panicked"))
+            }
+            None => Ok(()),
+        }
+    }
 }
-impl Drop for Display {
-    fn drop(&mut self) {
-        self.tx.lock().unwrap().send(None).unwrap();
-        self.join_handle.take().unwrap().join().unwrap();
+
+// a frame of any of the rgb interpretations the display can show
+struct DisplayFrame {
+    storage: GpuBuffer,
+    width: u64,
+    height: u64,
+    fps: FrameRate,
+    // the format of a single channel
+    format: Format,
+    future: Box<dyn GpuFuture>,
+}
+impl DisplayFrame {
+    fn from_payload(payload: &mut Payload, queue: Arc<Queue>) -> Result<Self> {
+        macro_rules! try_interpretation {
+            ($interpretation:ty, $format:expr) => {
+                if let Ok((frame, future)) =
+                    ensure_gpu_buffer::<$interpretation>(payload, queue.clone())
+                {
+                    return Ok(DisplayFrame {
+                        storage: frame.storage.clone(),
+                        width: frame.interp.width,
+                        height: frame.interp.height,
+                        fps: frame.interp.fps,
+                        format: $format,
+                        future: future.boxed(),
+                    });
+                }
+            };
+        }
+        try_interpretation!(Rgb, R8_UNORM);
+        try_interpretation!(Rgb16, R16_UNORM);
+        try_interpretation!(RgbF16, R16_SFLOAT);
+        try_interpretation!(RgbF32, R32_SFLOAT);
+        Err(anyhow!("cant display a payload of type {}", payload.type_name))
     }
 }
 
@@ -1,3 +1,5 @@
 pub mod bitdepth_convert;
+pub mod dark_frame;
 pub mod debayer;
 pub mod display;
+pub mod rgb_to_yuv;
@@ -0,0 +1,61 @@
+#version 450
+#extension GL_EXT_shader_explicit_arithmetic_types: enable
+#extension GL_EXT_shader_explicit_arithmetic_types_int8: require
+
+// every invocation handles one chroma sample and the luma samples that share it
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+layout(push_constant) uniform PushConstantData {
+    uint width;
+    uint height;
+    uint chroma_width;
+    uint chroma_height;
+    // how many pixels share one chroma sample horizontally and vertically
+    uint subsampling_x;
+    uint subsampling_y;
+    // the weights of red and blue in the luma
+    float kr;
+    float kb;
+} params;
+
+layout(set = 0, binding = 0) buffer readonly Source { uint8_t data[]; } source;
+layout(set = 0, binding = 1) buffer writeonly Sink   { uint8_t data[]; } sink;
+
+void main() {
+    uvec2 pos = gl_GlobalInvocationID.xy;
+    if (pos.x >= params.chroma_width || pos.y >= params.chroma_height) {
+        return;
+    }
+
+    float kg = 1. - params.kr - params.kb;
+    float cb_sum = 0.;
+    float cr_sum = 0.;
+    float n = 0.;
+    for (uint dy = 0; dy < params.subsampling_y; dy++) {
+        for (uint dx = 0; dx < params.subsampling_x; dx++) {
+            uint x = pos.x * params.subsampling_x + dx;
+            uint y = pos.y * params.subsampling_y + dy;
+            if (x >= params.width || y >= params.height) {
+                continue;
+            }
+            uint pixel = y * params.width + x;
+            vec3 rgb = vec3(
+                float(source.data[pixel * 3]),
+                float(source.data[pixel * 3 + 1]),
+                float(source.data[pixel * 3 + 2])
+            ) / 255.;
+
+            float luma = dot(rgb, vec3(params.kr, kg, params.kb));
+            sink.data[pixel] = uint8_t(round(16. + 219. * luma));
+            cb_sum += (rgb.b - luma) / (2. * (1. - params.kb));
+            cr_sum += (rgb.r - luma) / (2. * (1. - params.kr));
+            n += 1.;
+        }
+    }
+
+    uint luma_bytes = params.width * params.height;
+    uint chroma_bytes = params.chroma_width * params.chroma_height;
+    uint chroma = pos.y * params.chroma_width + pos.x;
+    sink.data[luma_bytes + chroma] = uint8_t(round(128. + 224. * cb_sum / n));
+    sink.data[luma_bytes + chroma_bytes + chroma] = uint8_t(round(128. + 224. * cr_sum / n));
+}
@@ -0,0 +1,161 @@
+use crate::pipeline_processing::{
+    buffers::GpuBuffer,
+    execute::ProcessingStageLockWaiter,
+    frame::{ChromaSubsampling, Frame, FrameInterpretation, Rgb, Yuv, YuvMatrix},
+    gpu_util::ensure_gpu_buffer,
+    parametrizable::{
+        ParameterType::StringParameter,
+        ParameterTypeDescriptor::Optional,
+        ParameterValue,
+        Parameterizable,
+        Parameters,
+        ParametersDescriptor,
+    },
+    payload::Payload,
+    port_type::PortType,
+    processing_context::ProcessingContext,
+    processing_node::ProcessingNode,
+};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferUsage, DeviceLocalBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage::OneTimeSubmit},
+    descriptor_set::persistent::PersistentDescriptorSet,
+    device::{Device, Queue},
+    pipeline::{ComputePipeline, PipelineBindPoint},
+    sync::GpuFuture,
+};
+
+mod compute_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/nodes_gpu/rgb_to_yuv.glsl"
+    }
+}
+
+pub struct GpuRgbToYuv {
+    device: Arc<Device>,
+    pipeline: Arc<ComputePipeline>,
+    queue: Arc<Queue>,
+    subsampling: ChromaSubsampling,
+    matrix: YuvMatrix,
+}
+
+crate::register_node!(GpuRgbToYuv);
+impl Parameterizable for GpuRgbToYuv {
+    const DESCRIPTION: Option<&'static str> =
+        Some("convert rgb frames to planar limited range yuv on the gpu");
+
+    fn describe_parameters() -> ParametersDescriptor {
+        ParametersDescriptor::new()
+            .with(
+                "subsampling",
+                Optional(StringParameter, ParameterValue::StringParameter("420".to_string())),
+            )
+            .with(
+                "matrix",
+                Optional(StringParameter, ParameterValue::StringParameter("bt709".to_string())),
+            )
+    }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> { Ok(vec![PortType::Rgb]) }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Yuv)
+    }
+    fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let (device, queues) = context.require_vulkan()?;
+        let queue = queues.iter().find(|&q| q.family().supports_compute()).unwrap().clone();
+
+        let pipeline = Arc::new({
+            let shader = compute_shader::Shader::load(device.clone()).unwrap();
+            ComputePipeline::new(device.clone(), &shader.main_entry_point(), &(), None, |_| {})
+                .unwrap()
+        });
+
+        Ok(GpuRgbToYuv {
+            device,
+            pipeline,
+            queue,
+            subsampling: parameters.get::<String>("subsampling")?.parse()?,
+            matrix: parameters.get::<String>("matrix")?.parse()?,
+        })
+    }
+}
+
+impl ProcessingNode for GpuRgbToYuv {
+    fn process(
+        &self,
+        input: &mut Payload,
+        _frame_lock: ProcessingStageLockWaiter,
+    ) -> Result<Option<Payload>> {
+        let (frame, fut) =
+            ensure_gpu_buffer::<Rgb>(input, self.queue.clone()).context("Wrong input format")?;
+        let interp = Yuv {
+            width: frame.interp.width,
+            height: frame.interp.height,
+            subsampling: self.subsampling,
+            matrix: self.matrix,
+            fps: frame.interp.fps,
+        };
+
+        let sink_buffer = DeviceLocalBuffer::<[u8]>::array(
+            self.device.clone(),
+            interp.required_bytes() as u64,
+            BufferUsage { storage_buffer: true, transfer_source: true, ..BufferUsage::none() },
+            std::iter::once(self.queue.family()),
+        )?;
+
+        let (chroma_width, chroma_height) = interp.chroma_size();
+        let (subsampling_x, subsampling_y) = interp.subsampling.factors();
+        let (kr, kb) = interp.matrix.coefficients();
+        let push_constants = compute_shader::ty::PushConstantData {
+            width: interp.width as u32,
+            height: interp.height as u32,
+            chroma_width: chroma_width as u32,
+            chroma_height: chroma_height as u32,
+            subsampling_x: subsampling_x as u32,
+            subsampling_y: subsampling_y as u32,
+            kr,
+            kb,
+        };
+
+        let layout = self.pipeline.layout().descriptor_set_layouts()[0].clone();
+        let set = Arc::new({
+            let mut builder = PersistentDescriptorSet::start(layout);
+            builder.add_buffer(frame.storage.untyped())?;
+            builder.add_buffer(sink_buffer.clone())?;
+            builder.build()?
+        });
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.device.clone(),
+            self.queue.family(),
+            OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .bind_pipeline_compute(self.pipeline.clone())
+            .dispatch([(chroma_width as u32 + 31) / 32, (chroma_height as u32 + 31) / 32, 1])?;
+        let command_buffer = builder.build()?;
+
+        let future =
+            fut.then_execute(self.queue.clone(), command_buffer)?.then_signal_fence_and_flush()?;
+
+        future.wait(None).unwrap();
+        Ok(Some(Payload::from(Frame {
+            interp,
+            storage: GpuBuffer::from(sink_buffer),
+            metadata: frame.metadata.clone(),
+        })))
+    }
+}
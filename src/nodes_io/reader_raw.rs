@@ -1,6 +1,7 @@
 use crate::pipeline_processing::{
     execute::ProcessingStageLockWaiter,
     frame::{Frame, FrameInterpretation, Raw},
+    metadata::FrameMetadata,
     parametrizable::{
         ParameterType::{BoolParameter, FloatRange, StringParameter},
         ParameterTypeDescriptor::{Mandatory, Optional},
@@ -10,12 +11,20 @@ use crate::pipeline_processing::{
         ParametersDescriptor,
     },
     payload::Payload,
+    port_type::PortType,
     processing_context::ProcessingContext,
     processing_node::ProcessingNode,
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use glob::glob;
-use std::{fs::File, io::Read, path::PathBuf, sync::Mutex, thread::sleep, time::Duration};
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread::sleep,
+    time::Duration,
+};
 
 pub struct RawBlobReader {
     file: Mutex<File>,
@@ -24,6 +33,7 @@ pub struct RawBlobReader {
     sleep: f64,
     context: ProcessingContext,
 }
+crate::register_node!(RawBlobReader);
 impl Parameterizable for RawBlobReader {
     const DESCRIPTION: Option<&'static str> =
         Some("read packed binary frames from a single file without headers or metadata");
@@ -34,6 +44,10 @@ impl Parameterizable for RawBlobReader {
             .with_raw_interpretation()
             .with("sleep", Optional(FloatRange(0., f64::MAX), ParameterValue::FloatRange(0.0)))
     }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> { Ok(vec![PortType::Empty]) }
+    fn output_type(parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::raw(parameters.get("bit-depth")?))
+    }
     fn from_parameters(options: &Parameters, context: ProcessingContext) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -62,13 +76,20 @@ impl ProcessingNode for RawBlobReader {
 
         let mut buffer =
             unsafe { self.context.get_uninit_cpu_buffer(self.interp.required_bytes()) };
-        let read_count =
-            buffer.as_mut_slice(|buffer| self.file.lock().unwrap().read(buffer).unwrap());
+        let (read_count, sequence_number) = buffer.as_mut_slice(|buffer| -> Result<_> {
+            let mut file = self.file.lock().unwrap();
+            let sequence_number = file.stream_position()? / buffer.len() as u64;
+            Ok((file.read(buffer)?, sequence_number))
+        })?;
 
         if read_count == 0 {
             Ok(None)
         } else if read_count == buffer.len() {
-            Ok(Some(Payload::from(Frame { storage: buffer, interp: self.interp })))
+            let metadata = FrameMetadata {
+                sequence_number: Some(sequence_number),
+                ..FrameMetadata::default()
+            };
+            Ok(Some(Payload::from(Frame { storage: buffer, interp: self.interp, metadata })))
         } else {
             Err(anyhow!("File could not be fully consumed. is the resolution set right?"))
         }
@@ -76,14 +97,20 @@ impl ProcessingNode for RawBlobReader {
     fn size_hint(&self) -> Option<u64> { Some(self.frame_count) }
 }
 
+/// Reads every file that matches the pattern as a frame, in alphabetical order.
+/// If the file names end in a frame number (like `frame_0012.raw12`), missing
+/// numbers between two files are recorded as dropped frames in the metadata.
 pub struct RawDirectoryReader {
     files: Vec<PathBuf>,
+    // the number at the end of the name of every file
+    file_numbers: Vec<Option<u64>>,
     payload_vec: Mutex<Vec<Option<Payload>>>,
     do_loop: bool,
     sleep: f64,
     interp: Raw,
     context: ProcessingContext,
 }
+crate::register_node!(RawDirectoryReader);
 impl Parameterizable for RawDirectoryReader {
     const DESCRIPTION: Option<&'static str> =
         Some("read packed binary frames without headers or metadata from a directory");
@@ -95,6 +122,10 @@ impl Parameterizable for RawDirectoryReader {
             .with("loop", Optional(BoolParameter, ParameterValue::BoolParameter(false)))
             .with("sleep", Optional(FloatRange(0., f64::MAX), ParameterValue::FloatRange(0.0)))
     }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> { Ok(vec![PortType::Empty]) }
+    fn output_type(parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::raw(parameters.get("bit-depth")?))
+    }
     fn from_parameters(options: &Parameters, context: ProcessingContext) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -102,8 +133,10 @@ impl Parameterizable for RawDirectoryReader {
         let file_pattern: String = options.get("file-pattern")?;
         let files = glob(&file_pattern)?.collect::<std::result::Result<Vec<_>, _>>()?;
         let frame_count = files.len();
+        let file_numbers = files.iter().map(|path| file_number(path)).collect();
         Ok(Self {
             files,
+            file_numbers,
             interp: options.get_raw_interpretation()?,
             do_loop: options.get("loop")?,
             payload_vec: Mutex::new((0..frame_count).map(|_| None).collect()),
@@ -131,15 +164,27 @@ impl ProcessingNode for RawDirectoryReader {
             }
             ref mut none => {
                 if self.do_loop || frame_number <= self.files.len() {
-                    let path = &self.files[(frame_number - 1) as usize % self.files.len()];
-                    let mut file = File::open(path)?;
+                    let file_number = (frame_number - 1) as usize % self.files.len();
+                    let path = &self.files[file_number];
+                    let mut file = File::open(path)
+                        .with_context(|| format!("cant open {}", path.display()))?;
+                    let metadata = FrameMetadata {
+                        timestamp: file.metadata().and_then(|metadata| metadata.modified()).ok(),
+                        sequence_number: Some(
+                            self.file_numbers[file_number].unwrap_or(file_number as u64),
+                        ),
+                        dropped_before: self.dropped_before(file_number),
+                        ..FrameMetadata::default()
+                    }
+                    .with_tag("file", path.display());
 
                     let mut buffer =
                         unsafe { self.context.get_uninit_cpu_buffer(self.interp.required_bytes()) };
-                    buffer.as_mut_slice(|buffer| {
-                        file.read_exact(buffer).unwrap();
-                    });
-                    let payload = Payload::from(Frame { storage: buffer, interp: self.interp });
+                    buffer.as_mut_slice(|buffer| file.read_exact(buffer)).with_context(|| {
+                        format!("cant read a whole frame from {}", path.display())
+                    })?;
+                    let payload =
+                        Payload::from(Frame { storage: buffer, interp: self.interp, metadata });
 
                     if self.do_loop {
                         *none = Some(payload.clone());
@@ -160,3 +205,24 @@ impl ProcessingNode for RawDirectoryReader {
         }
     }
 }
+
+impl RawDirectoryReader {
+    // the frame numbers that are missing between the previous file and this one
+    fn dropped_before(&self, file: usize) -> u64 {
+        match (
+            file.checked_sub(1).and_then(|previous| self.file_numbers[previous]),
+            self.file_numbers[file],
+        ) {
+            (Some(previous), Some(number)) if number > previous => number - previous - 1,
+            _ => 0,
+        }
+    }
+}
+
+// the number at the end of a file name without its extension, like 12 for
+// frame_0012.raw12
+fn file_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    stem[prefix.len()..].parse().ok()
+}
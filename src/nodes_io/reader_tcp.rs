@@ -1,6 +1,7 @@
 use crate::pipeline_processing::{
     execute::ProcessingStageLockWaiter,
     frame::{Frame, FrameInterpretation, Raw},
+    metadata::FrameMetadata,
     parametrizable::{
         ParameterType::StringParameter,
         ParameterTypeDescriptor::Mandatory,
@@ -9,17 +10,28 @@ use crate::pipeline_processing::{
         ParametersDescriptor,
     },
     payload::Payload,
+    port_type::PortType,
     processing_context::ProcessingContext,
     processing_node::ProcessingNode,
 };
 use anyhow::Result;
-use std::{io::Read, net::TcpStream, sync::Mutex};
+use std::{
+    io::Read,
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
 
 pub struct TcpReader {
     pub tcp_connection: Mutex<TcpStream>,
     interp: Raw,
+    frames_received: AtomicU64,
     context: ProcessingContext,
 }
+crate::register_node!(TcpReader);
 impl Parameterizable for TcpReader {
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::new()
@@ -27,6 +39,10 @@ impl Parameterizable for TcpReader {
             .with_raw_interpretation()
     }
 
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> { Ok(vec![PortType::Empty]) }
+    fn output_type(parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::raw(parameters.get("bit-depth")?))
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
@@ -34,6 +50,7 @@ impl Parameterizable for TcpReader {
         Ok(Self {
             tcp_connection: Mutex::new(TcpStream::connect(parameters.get::<String>("address")?)?),
             interp: parameters.get_raw_interpretation()?,
+            frames_received: AtomicU64::new(0),
             context,
         })
     }
@@ -45,7 +62,16 @@ impl ProcessingNode for TcpReader {
         _frame_lock: ProcessingStageLockWaiter,
     ) -> Result<Option<Payload>> {
         let mut buf = unsafe { self.context.get_uninit_cpu_buffer(self.interp.required_bytes()) };
-        buf.as_mut_slice(|slice| self.tcp_connection.lock().unwrap().read_exact(slice))?;
-        Ok(Some(Payload::from(Frame { storage: buf, interp: self.interp })))
+        let metadata = buf.as_mut_slice(|slice| -> Result<_> {
+            let mut tcp_connection = self.tcp_connection.lock().unwrap();
+            tcp_connection.read_exact(slice)?;
+            // the stream has no timestamps, so the time of arrival is the best guess
+            Ok(FrameMetadata {
+                timestamp: Some(SystemTime::now()),
+                sequence_number: Some(self.frames_received.fetch_add(1, Ordering::Relaxed)),
+                ..FrameMetadata::default()
+            })
+        })?;
+        Ok(Some(Payload::from(Frame { storage: buf, interp: self.interp, metadata })))
     }
 }
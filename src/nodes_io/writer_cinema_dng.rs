@@ -1,7 +1,7 @@
 use crate::pipeline_processing::{
     buffers::CpuBuffer,
     execute::ProcessingStageLockWaiter,
-    frame::Raw,
+    frame::{BitPacking, CfaDescriptor, Raw},
     parametrizable::{
         ParameterType::StringParameter,
         ParameterTypeDescriptor::Mandatory,
@@ -10,13 +10,17 @@ use crate::pipeline_processing::{
         ParametersDescriptor,
     },
     payload::Payload,
+    port_type::PortType,
     processing_context::ProcessingContext,
     processing_node::ProcessingNode,
 };
 use anyhow::{Context, Result};
-use std::fs::create_dir;
+use std::{
+    fs::create_dir,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tiff_encoder::{
-    ifd::{tags, values::Offsets, Ifd},
+    ifd::{tags, types, values::Offsets, Ifd},
     write::{Datablock, EndianFile},
     TiffFile,
     ASCII,
@@ -26,7 +30,6 @@ use tiff_encoder::{
     SHORT,
     SRATIONAL,
 };
-use vulkano::buffer::TypedBufferAccess;
 
 /// A writer, that writes cinemaDNG (a folder with DNG files)
 pub struct CinemaDngWriter {
@@ -34,11 +37,18 @@ pub struct CinemaDngWriter {
     context: ProcessingContext,
 }
 
+crate::register_node!(CinemaDngWriter);
 impl Parameterizable for CinemaDngWriter {
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::new().with("path", Mandatory(StringParameter))
     }
 
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::any_raw()])
+    }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Empty)
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
@@ -58,60 +68,168 @@ impl ProcessingNode for CinemaDngWriter {
         let frame = self.context.ensure_cpu_buffer::<Raw>(input).context("Wrong input format")?;
         let current_frame_number = frame_lock.frame();
 
-        let cfa_pattern = match (frame.interp.cfa.first_is_red_x, frame.interp.cfa.first_is_red_y) {
-            (true, true) => BYTE![0, 1, 1, 2],
-            (true, false) => BYTE![1, 0, 2, 1],
-            (false, true) => BYTE![1, 2, 0, 1],
-            (false, false) => BYTE![2, 1, 1, 0],
+        // dng only knows tightly packed msb first samples and 16 bit words (in the byte
+        // order of the file, which is little endian)
+        let (storage, bits_per_sample) = match frame.interp.packing {
+            BitPacking::MsbFirst => (frame.storage.clone(), frame.interp.bit_depth),
+            BitPacking::U16Le => (frame.storage.clone(), 16),
+            BitPacking::LsbFirst => {
+                let mut storage =
+                    unsafe { self.context.get_uninit_cpu_buffer(frame.storage.len()) };
+                storage.as_mut_slice(|repacked| {
+                    frame.storage.as_slice(|data| {
+                        let mut samples =
+                            Vec::with_capacity((frame.interp.width * frame.interp.height) as usize);
+                        BitPacking::LsbFirst.read_samples(frame.interp.bit_depth, data, |sample| {
+                            samples.push(sample)
+                        });
+                        BitPacking::MsbFirst.write_samples(
+                            frame.interp.bit_depth,
+                            samples,
+                            repacked,
+                        );
+                    })
+                });
+                (storage, frame.interp.bit_depth)
+            }
+        };
+
+        let fps = frame.interp.fps;
+        let mut ifd = Ifd::new()
+            .with_entry(50706, BYTE![1, 4, 0, 0])  // DNG version
+            .with_entry(tags::Compression, SHORT![1]) // No compression
+            .with_entry(tags::SamplesPerPixel, SHORT![1])
+            .with_entry(tags::NewSubfileType, LONG![0])
+            .with_entry(tags::XResolution, RATIONAL![(1, 1)])
+            .with_entry(tags::YResolution, RATIONAL![(1, 1)])
+            .with_entry(tags::ResolutionUnit, SHORT!(1))
+            .with_entry(tags::FillOrder, SHORT![1])
+            .with_entry(tags::Orientation, SHORT![1])
+            .with_entry(tags::PlanarConfiguration, SHORT![1])
+
+            .with_entry(tags::Make, ASCII!["Apertus"])
+            .with_entry(tags::Model, ASCII!["AXIOM"])
+            .with_entry(50708, ASCII!("Apertus AXIOM")) // unique camera model
+            .with_entry(tags::Software, ASCII!["axiom-recorder"])
+
+            // color matrix from https://github.com/apertus-open-source-cinema/misc-tools-utilities/blob/8c8e9fca96b4b3fec50756fd7a72be6ea5c7b77c/raw2dng/raw2dng.c#L46-L49
+            .with_entry(50721, SRATIONAL![  // ColorMatrix1
+                    (11038, 10000), (3184, 10000), (1009, 10000),
+                    (3284, 10000), (11499, 10000), (1737, 10000),
+                    (1283, 10000), (3550, 10000), (5967, 10000)
+           ])
+
+            .with_entry(51044, SRATIONAL![(fps.numerator() as i32, fps.denominator() as i32)])// FrameRate
+
+            .with_entry(tags::ImageLength, LONG![frame.interp.height as u32])
+            .with_entry(tags::ImageWidth, LONG![frame.interp.width as u32])
+            .with_entry(tags::RowsPerStrip, LONG![frame.interp.height as u32])
+            .with_entry(tags::StripByteCounts, LONG![storage.len() as u32])
+            .with_entry(tags::BitsPerSample, SHORT![bits_per_sample as u16])
+            .with_entry(tags::StripOffsets, Offsets::single(storage));
+
+        ifd = match frame.interp.cfa {
+            // LinearRaw
+            CfaDescriptor::Mono => ifd.with_entry(tags::PhotometricInterpretation, SHORT![34892]),
+            cfa @ CfaDescriptor::Pattern { .. } => {
+                let (width, height) = cfa.size();
+                // CFARepeatPatternDim is (rows, columns), CFAPattern uses R=0, G=1, B=2
+                let pattern = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| cfa.color_at(x, y).unwrap() as u8))
+                    .collect::<Vec<_>>();
+                ifd.with_entry(tags::PhotometricInterpretation, SHORT![32803]) // CFA
+                    .with_entry(33421, types::SHORT::values(vec![height as u16, width as u16]))
+                    .with_entry(33422, types::BYTE::values(pattern))
+            }
         };
+        let metadata = &frame.metadata;
+        if let Some(levels) = metadata.levels {
+            // BlackLevelRepeatDim and BlackLevel, with a value for every position of the cfa
+            // pattern
+            let (width, height) = frame.interp.cfa.size();
+            let black_levels = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let color = frame.interp.cfa.color_at(x, y).map_or(0, |color| color as usize);
+                    ((levels.black[color].max(0.0) * 100.0).round() as u32, 100)
+                })
+                .collect::<Vec<_>>();
+            ifd = ifd
+                .with_entry(50713, types::SHORT::values(vec![height as u16, width as u16]))
+                .with_entry(50714, types::RATIONAL::values(black_levels))
+                // WhiteLevel
+                .with_entry(50717, LONG![levels.white.round() as u32]);
+        } else if bits_per_sample != frame.interp.bit_depth {
+            // WhiteLevel
+            ifd = ifd.with_entry(50717, LONG![(1u32 << frame.interp.bit_depth) - 1]);
+        }
+
+        if let Some(timestamp) = metadata.timestamp {
+            ifd = ifd.with_entry(tags::DateTime, ASCII![&tiff_date_time(timestamp)]);
+        }
+        // TimeCodes (smpte 12m, non drop frame, bcd coded), counted from the start of
+        // the recording
+        let frame_number = current_frame_number - 1;
+        let nominal_fps = fps.nominal() as u64;
+        let bcd = |value: u64| (((value / 10) << 4) | (value % 10)) as u8;
+        let seconds = frame_number / nominal_fps;
+        ifd = ifd.with_entry(
+            51043,
+            types::BYTE::values(vec![
+                bcd(frame_number % nominal_fps),
+                bcd(seconds % 60),
+                bcd(seconds / 60 % 60),
+                bcd(seconds / 3600 % 24),
+                0,
+                0,
+                0,
+                0,
+            ]),
+        );
+        if let Some(sequence_number) = metadata.sequence_number {
+            ifd = ifd.with_entry(37393, LONG![sequence_number as u32]); // ImageNumber
+        }
+        if !metadata.is_empty() {
+            ifd = ifd.with_entry(tags::ImageDescription, ASCII![&metadata.to_json().to_string()]);
+        }
 
-        TiffFile::new(
-            Ifd::new()
-                .with_entry(50706, BYTE![1, 4, 0, 0])  // DNG version
-                .with_entry(tags::Compression, SHORT![1]) // No compression
-                .with_entry(tags::SamplesPerPixel, SHORT![1])
-                .with_entry(tags::NewSubfileType, LONG![0])
-                .with_entry(tags::XResolution, RATIONAL![(1, 1)])
-                .with_entry(tags::YResolution, RATIONAL![(1, 1)])
-                .with_entry(tags::ResolutionUnit, SHORT!(1))
-                .with_entry(tags::FillOrder, SHORT![1])
-                .with_entry(tags::Orientation, SHORT![1])
-                .with_entry(tags::PlanarConfiguration, SHORT![1])
-
-                .with_entry(tags::Make, ASCII!["Apertus"])
-                .with_entry(tags::Model, ASCII!["AXIOM"])
-                .with_entry(50708, ASCII!("Apertus AXIOM")) // unique camera model
-                .with_entry(tags::Software, ASCII!["axiom-recorder"])
-
-                .with_entry(tags::PhotometricInterpretation, SHORT![32803]) // Black is zero
-                .with_entry(33421, SHORT![2, 2]) // CFARepeatPatternDim
-                .with_entry(33422, cfa_pattern) // CFAPattern (R=0, G=1, B=2)
-
-                // color matrix from https://github.com/apertus-open-source-cinema/misc-tools-utilities/blob/8c8e9fca96b4b3fec50756fd7a72be6ea5c7b77c/raw2dng/raw2dng.c#L46-L49
-                .with_entry(50721, SRATIONAL![  // ColorMatrix1
-                        (11038, 10000), (3184, 10000), (1009, 10000),
-                        (3284, 10000), (11499, 10000), (1737, 10000),
-                        (1283, 10000), (3550, 10000), (5967, 10000)
-               ])
-
-                .with_entry(51044, SRATIONAL![((frame.interp.fps * 10000.0) as i32, 10000)])// FrameRate
-
-                .with_entry(tags::ImageLength, LONG![frame.interp.height as u32])
-                .with_entry(tags::ImageWidth, LONG![frame.interp.width as u32])
-                .with_entry(tags::RowsPerStrip, LONG![frame.interp.height as u32])
-                .with_entry(tags::StripByteCounts, LONG![frame.storage.len() as u32])
-                .with_entry(tags::BitsPerSample, SHORT![frame.interp.bit_depth as u16])
-                .with_entry(tags::StripOffsets, Offsets::single(frame.storage.clone()))
-                .single(),
-        )
-        .write_to(format!("{}/{:06}.dng", &self.dir_path, current_frame_number))?;
+        TiffFile::new(ifd.single())
+            .write_to(format!("{}/{:06}.dng", &self.dir_path, current_frame_number))?;
         Ok(Some(Payload::empty()))
     }
 }
 
+// formats a timestamp as YYYY:MM:DD HH:MM:SS in utc, as required by the
+// DateTime tag
+fn tiff_date_time(timestamp: SystemTime) -> String {
+    let seconds = timestamp.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+    // days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
 
 impl Datablock for CpuBuffer {
-    fn size(&self) -> u32 { self.cpu_accessible_buffer().len() as u32 }
+    fn size(&self) -> u32 { self.len() as u32 }
 
     fn write_to(self, file: &mut EndianFile) -> std::io::Result<()> {
         self.as_slice(|slice| file.write_all_u8(slice))
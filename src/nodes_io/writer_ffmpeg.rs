@@ -1,6 +1,7 @@
 use crate::pipeline_processing::{
+    buffers::CpuBuffer,
     execute::ProcessingStageLockWaiter,
-    frame::Rgb,
+    frame::{ChromaSubsampling, FrameRate, Rgb, Rgb16, RgbF32, Rgba16, Yuv, YuvMatrix},
     parametrizable::{
         ParameterType::StringParameter,
         ParameterTypeDescriptor::{Mandatory, Optional},
@@ -10,10 +11,13 @@ use crate::pipeline_processing::{
         ParametersDescriptor,
     },
     payload::Payload,
+    port_type::PortType,
     processing_context::ProcessingContext,
     processing_node::ProcessingNode,
 };
 use anyhow::{anyhow, Context, Result};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
 use std::{
     io::Write,
     process::{Child, Command, Stdio},
@@ -23,10 +27,11 @@ use std::{
 pub struct FfmpegWriter {
     output: String,
     input_options: String,
-    interp: Arc<Mutex<Option<Rgb>>>,
+    video_format: Arc<Mutex<Option<VideoFormat>>>,
     child: Arc<Mutex<Option<Child>>>,
     context: ProcessingContext,
 }
+crate::register_node!(FfmpegWriter);
 impl Parameterizable for FfmpegWriter {
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::new().with("output", Mandatory(StringParameter)).with(
@@ -34,13 +39,19 @@ impl Parameterizable for FfmpegWriter {
             Optional(StringParameter, ParameterValue::StringParameter("".to_string())),
         )
     }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::Rgb, PortType::Rgb16, PortType::Rgba16, PortType::RgbF32, PortType::Yuv])
+    }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Empty)
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
     {
         Ok(Self {
             child: Arc::new(Mutex::new(None)),
-            interp: Arc::new(Mutex::new(None)),
+            video_format: Arc::new(Mutex::new(None)),
             output: parameters.get("output")?,
             input_options: parameters.get("input-options")?,
             context,
@@ -54,19 +65,26 @@ impl ProcessingNode for FfmpegWriter {
         frame_lock: ProcessingStageLockWaiter,
     ) -> Result<Option<Payload>> {
         frame_lock.wait();
-        let frame = self.context.ensure_cpu_buffer::<Rgb>(input).context("Wrong input format")?;
+        let (storage, format) = VideoFormat::of_frame(&self.context, input)?;
 
         {
-            let mut interp = self.interp.lock().unwrap();
-            if interp.is_none() {
-                let child = Command::new("ffmpeg")
+            let mut video_format = self.video_format.lock().unwrap();
+            if video_format.is_none() {
+                let mut command = Command::new("ffmpeg");
+                // ffmpeg would otherwise also get our SIGINT and stop before we fed it the
+                // frames still in flight
+                #[cfg(unix)]
+                command.process_group(0);
+                let child = command
                     .args(
                         shlex::split(&format!(
-                        "{} -f rawvideo -framerate {} -video_size {}x{} -pixel_format rgb24 -i - {}",
+                        "{} {} -f rawvideo -framerate {} -video_size {}x{} -pixel_format {} -i - {}",
                         self.input_options,
-                        frame.interp.fps,
-                        frame.interp.width,
-                        frame.interp.height,
+                        format.color_options,
+                        format.fps,
+                        format.width,
+                        format.height,
+                        format.pixel_format,
                         self.output
                     ))
                         .unwrap(),
@@ -74,21 +92,15 @@ impl ProcessingNode for FfmpegWriter {
                     .stdin(Stdio::piped())
                     .spawn()?;
                 *self.child.lock().unwrap() = Some(child);
-                *interp = Some(frame.interp)
-            } else if interp.is_some() {
-                let Rgb { width, height, fps } = interp.unwrap();
-                if width != frame.interp.width
-                    || height != frame.interp.height
-                    || fps != frame.interp.fps
-                {
-                    return Err(anyhow!(
-                        "the resolution or the framerate MAY NOT change during an ffmpeg encoding session"
-                    ));
-                }
+                *video_format = Some(format)
+            } else if *video_format != Some(format) {
+                return Err(anyhow!(
+                    "the resolution, the pixel format or the framerate MAY NOT change during an ffmpeg encoding session"
+                ));
             }
         }
 
-        frame.storage.as_slice(|slice| {
+        storage.as_slice(|slice| {
             self.child
                 .clone()
                 .lock()
@@ -103,7 +115,80 @@ impl ProcessingNode for FfmpegWriter {
 
         Ok(Some(Payload::empty()))
     }
+
+    fn flush(&self) -> Result<()> {
+        if let Some(stdin) = self.child.lock().unwrap().as_mut().and_then(|c| c.stdin.as_mut()) {
+            stdin.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<()> {
+        // no frame ever arrived, so ffmpeg was never started
+        let mut child = match self.child.lock().unwrap().take() {
+            Some(child) => child,
+            None => return Ok(()),
+        };
+
+        // closing stdin signals ffmpeg the end of the video
+        drop(child.stdin.take());
+        let status = child.wait().context("error while waiting for ffmpeg to exit")?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("ffmpeg exited with {}", status))
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct VideoFormat {
+    width: u64,
+    height: u64,
+    fps: FrameRate,
+    pixel_format: &'static str,
+    // tells ffmpeg how to interpret yuv input
+    color_options: &'static str,
 }
-impl Drop for FfmpegWriter {
-    fn drop(&mut self) { self.child.lock().unwrap().as_mut().unwrap().wait().unwrap(); }
+impl VideoFormat {
+    fn of_frame(context: &ProcessingContext, input: &mut Payload) -> Result<(CpuBuffer, Self)> {
+        macro_rules! try_interpretation {
+            ($interpretation:ty, $pixel_format:expr) => {
+                if let Ok(frame) = context.ensure_cpu_buffer::<$interpretation>(input) {
+                    let format = VideoFormat {
+                        width: frame.interp.width,
+                        height: frame.interp.height,
+                        fps: frame.interp.fps,
+                        pixel_format: $pixel_format,
+                        color_options: "",
+                    };
+                    return Ok((frame.storage.clone(), format));
+                }
+            };
+        }
+        try_interpretation!(Rgb, "rgb24");
+        try_interpretation!(Rgb16, "rgb48le");
+        try_interpretation!(Rgba16, "rgba64le");
+        // needs ffmpeg 6.1 or newer. there is no pixel format for half float rgb
+        // (without alpha)
+        try_interpretation!(RgbF32, "rgbf32le");
+        if let Ok(frame) = context.ensure_cpu_buffer::<Yuv>(input) {
+            let format = VideoFormat {
+                width: frame.interp.width,
+                height: frame.interp.height,
+                fps: frame.interp.fps,
+                pixel_format: match frame.interp.subsampling {
+                    ChromaSubsampling::Yuv420 => "yuv420p",
+                    ChromaSubsampling::Yuv422 => "yuv422p",
+                    ChromaSubsampling::Yuv444 => "yuv444p",
+                },
+                color_options: match frame.interp.matrix {
+                    YuvMatrix::Bt601 => "-color_range tv -colorspace smpte170m",
+                    YuvMatrix::Bt709 => "-color_range tv -colorspace bt709",
+                },
+            };
+            return Ok((frame.storage.clone(), format));
+        }
+        Err(anyhow!("ffmpeg cant encode payloads of type {}", input.type_name))
+    }
 }
@@ -1,6 +1,6 @@
 use crate::pipeline_processing::{
     execute::ProcessingStageLockWaiter,
-    frame::Rgb,
+    frame::{ChromaSubsampling, Rgb, Rgba16, Yuv, YuvMatrix},
     parametrizable::{
         ParameterType::StringParameter,
         ParameterTypeDescriptor::Mandatory,
@@ -9,25 +9,44 @@ use crate::pipeline_processing::{
         ParametersDescriptor,
     },
     payload::Payload,
+    port_type::PortType,
     processing_context::ProcessingContext,
     processing_node::ProcessingNode,
 };
 use anyhow::{anyhow, Context, Result};
-use gstreamer::{prelude::*, Buffer, Format, Fraction, ParseContext, Pipeline};
+use gstreamer::{prelude::*, Buffer, ClockTime, Format, Fraction, ParseContext, Pipeline};
 use gstreamer_app::AppSrc;
-use gstreamer_video::{VideoFormat, VideoInfo};
-use std::thread::{spawn, JoinHandle};
+use gstreamer_video::{
+    VideoColorMatrix,
+    VideoColorPrimaries,
+    VideoColorRange,
+    VideoColorimetry,
+    VideoFormat,
+    VideoInfo,
+    VideoTransferFunction,
+};
+use std::{
+    sync::Mutex,
+    thread::{spawn, JoinHandle},
+};
 
 
 pub struct GstWriter {
     appsrc: AppSrc,
-    thread_handle: Option<JoinHandle<()>>,
+    thread_handle: Mutex<Option<JoinHandle<Result<()>>>>,
     context: ProcessingContext,
 }
+crate::register_node!(GstWriter);
 impl Parameterizable for GstWriter {
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::new().with("pipeline", Mandatory(StringParameter))
     }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(vec![PortType::Rgb, PortType::Rgba16, PortType::Yuv])
+    }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Empty)
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
@@ -47,9 +66,7 @@ impl Parameterizable for GstWriter {
         let appsrc =
             pipeline.children().into_iter().last().unwrap().dynamic_cast::<AppSrc>().unwrap();
 
-        let thread_handle = Some(spawn(move || {
-            main_loop(pipeline).unwrap();
-        }));
+        let thread_handle = Mutex::new(Some(spawn(move || main_loop(pipeline))));
 
         Ok(Self { appsrc, thread_handle, context })
     }
@@ -59,32 +76,105 @@ impl ProcessingNode for GstWriter {
     fn process(
         &self,
         input: &mut Payload,
-        _frame_lock: ProcessingStageLockWaiter,
+        frame_lock: ProcessingStageLockWaiter,
     ) -> Result<Option<Payload>> {
-        let frame = self.context.ensure_cpu_buffer::<Rgb>(input).context("Wrong input format")?;
-
-        let video_info = VideoInfo::builder(
-            VideoFormat::Rgb,
-            frame.interp.width as u32,
-            frame.interp.height as u32,
-        )
-        .fps(Fraction::new(2, 1))
-        .build()
-        .expect("Failed to create video info");
+        frame_lock.wait();
+        // gstreamer has no packed 16 bit rgb format without alpha and no float formats
+        let (storage, format, width, height, fps, colorimetry) = if let Ok(frame) =
+            self.context.ensure_cpu_buffer::<Rgb>(input)
+        {
+            (
+                frame.storage.clone(),
+                VideoFormat::Rgb,
+                frame.interp.width,
+                frame.interp.height,
+                frame.interp.fps,
+                None,
+            )
+        } else if let Ok(frame) = self.context.ensure_cpu_buffer::<Yuv>(input) {
+            let format = match frame.interp.subsampling {
+                ChromaSubsampling::Yuv420 => VideoFormat::I420,
+                ChromaSubsampling::Yuv422 => VideoFormat::Y42b,
+                ChromaSubsampling::Yuv444 => VideoFormat::Y444,
+            };
+            let colorimetry = match frame.interp.matrix {
+                YuvMatrix::Bt601 => VideoColorimetry::new(
+                    VideoColorRange::Range16_235,
+                    VideoColorMatrix::Bt601,
+                    VideoTransferFunction::Bt709,
+                    VideoColorPrimaries::Smpte170m,
+                ),
+                YuvMatrix::Bt709 => VideoColorimetry::new(
+                    VideoColorRange::Range16_235,
+                    VideoColorMatrix::Bt709,
+                    VideoTransferFunction::Bt709,
+                    VideoColorPrimaries::Bt709,
+                ),
+            };
+            (
+                frame.storage.clone(),
+                format,
+                frame.interp.width,
+                frame.interp.height,
+                frame.interp.fps,
+                Some(colorimetry),
+            )
+        } else {
+            let frame =
+                self.context.ensure_cpu_buffer::<Rgba16>(input).context("Wrong input format")?;
+            // only known to gstreamer 1.20 and newer
+            let format = VideoFormat::from_string("RGBA64_LE");
+            if format == VideoFormat::Unknown {
+                return Err(anyhow!("this gstreamer version doesnt support RGBA64_LE"));
+            }
+            (
+                frame.storage.clone(),
+                format,
+                frame.interp.width,
+                frame.interp.height,
+                frame.interp.fps,
+                None,
+            )
+        };
+
+        let mut video_info = VideoInfo::builder(format, width as u32, height as u32)
+            .fps(Fraction::new(fps.numerator() as i32, fps.denominator() as i32));
+        if let Some(colorimetry) = &colorimetry {
+            video_info = video_info.colorimetry(colorimetry);
+        }
+        let video_info = video_info.build().expect("Failed to create video info");
         self.appsrc.set_caps(Some(&video_info.to_caps().unwrap()));
         self.appsrc.set_property("format", Format::Time)?;
         // TODO: save the copy
-        let vec = frame.storage.as_slice(|s| s.to_vec());
-        let buffer = Buffer::from_slice(vec);
+        let vec = storage.as_slice(|s| s.to_vec());
+        let mut buffer = Buffer::from_slice(vec);
+        {
+            // the timestamps are derived from the frame number, so they dont drift for
+            // fractional frame rates
+            let frame_number = frame_lock.frame() - 1;
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(ClockTime::from_nseconds(
+                fps.time_of_frame(frame_number).as_nanos() as u64
+            ));
+            buffer.set_duration(ClockTime::from_nseconds(
+                (fps.time_of_frame(frame_number + 1) - fps.time_of_frame(frame_number)).as_nanos()
+                    as u64,
+            ));
+        }
         self.appsrc.push_buffer(buffer)?;
 
         Ok(Some(Payload::empty()))
     }
-}
-impl Drop for GstWriter {
-    fn drop(&mut self) {
-        self.appsrc.end_of_stream().unwrap();
-        self.thread_handle.take().unwrap().join().unwrap();
+
+    fn finish(&self) -> Result<()> {
+        let thread_handle = match self.thread_handle.lock().unwrap().take() {
+            Some(thread_handle) => thread_handle,
+            None => return Ok(()),
+        };
+        self.appsrc
+            .end_of_stream()
+            .map_err(|e| anyhow!("cant send end of stream to the gstreamer pipeline: {:?}", e))?;
+        thread_handle.join().map_err(|_| anyhow!("the gstreamer thread panicked"))?
     }
 }
 
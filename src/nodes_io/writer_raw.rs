@@ -18,26 +18,46 @@ use crate::pipeline_processing::{
 use anyhow::{anyhow, Result};
 
 use crate::pipeline_processing::{
-    frame::{Raw, Rgb},
+    buffers::CpuBuffer,
+    frame::{Raw, Rgb, Rgb16, RgbF16, RgbF32, Rgba, Rgba16, Yuv},
+    metadata::FrameMetadata,
     payload::Payload,
+    port_type::PortType,
     processing_context::ProcessingContext,
 };
 
 
 pub struct RawBlobWriter {
     file: Arc<Mutex<File>>,
+    metadata_path: String,
+    metadata_file: Mutex<Option<File>>,
     context: ProcessingContext,
 }
+crate::register_node!(RawBlobWriter);
 impl Parameterizable for RawBlobWriter {
+    const DESCRIPTION: Option<&'static str> = Some(
+        "write frames into a single file without headers. the frame metadata is written to \
+         <path>.metadata.jsonl",
+    );
+
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::new().with("path", Mandatory(StringParameter))
     }
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(ALL_FRAME_TYPES.to_vec())
+    }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Empty)
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
     {
+        let path = parameters.get::<String>("path")?;
         Ok(Self {
-            file: Arc::new(Mutex::new(File::create(parameters.get::<String>("path")?)?)),
+            file: Arc::new(Mutex::new(File::create(&path)?)),
+            metadata_path: format!("{}.metadata.jsonl", path),
+            metadata_file: Mutex::new(None),
             context,
         })
     }
@@ -46,14 +66,20 @@ impl ProcessingNode for RawBlobWriter {
     fn process(
         &self,
         input: &mut Payload,
-        _frame_lock: ProcessingStageLockWaiter,
+        frame_lock: ProcessingStageLockWaiter,
     ) -> Result<Option<Payload>> {
-        if let Ok(frame) = self.context.ensure_cpu_buffer::<Rgb>(input) {
-            frame.storage.as_slice(|slice| self.file.lock().unwrap().write_all(slice))?;
-        } else if let Ok(frame) = self.context.ensure_cpu_buffer::<Raw>(input) {
-            frame.storage.as_slice(|slice| self.file.lock().unwrap().write_all(slice))?;
-        } else {
-            return Err(anyhow!("unknown input format {}", input.type_name));
+        let (storage, metadata) = frame_data(&self.context, input)?;
+        storage.as_slice(|slice| self.file.lock().unwrap().write_all(slice))?;
+
+        if !metadata.is_empty() {
+            // the side car file is only created once there is something to put into it
+            let mut metadata_file = self.metadata_file.lock().unwrap();
+            if metadata_file.is_none() {
+                *metadata_file = Some(File::create(&self.metadata_path)?);
+            }
+            let mut line = metadata.to_json();
+            line["frame"] = frame_lock.frame().into();
+            writeln!(metadata_file.as_mut().unwrap(), "{}", line)?;
         }
         Ok(Some(Payload::empty()))
     }
@@ -63,11 +89,23 @@ pub struct RawDirectoryWriter {
     dir_path: String,
     context: ProcessingContext,
 }
+crate::register_node!(RawDirectoryWriter);
 impl Parameterizable for RawDirectoryWriter {
+    const DESCRIPTION: Option<&'static str> = Some(
+        "write every frame into its own file without headers. the frame metadata is written to a \
+         .json file next to it",
+    );
+
     fn describe_parameters() -> ParametersDescriptor {
         ParametersDescriptor::new().with("path", Mandatory(StringParameter))
     }
 
+    fn input_types(_parameters: &Parameters) -> Result<Vec<PortType>> {
+        Ok(ALL_FRAME_TYPES.to_vec())
+    }
+    fn output_type(_parameters: &Parameters, _input: PortType) -> Result<PortType> {
+        Ok(PortType::Empty)
+    }
     fn from_parameters(parameters: &Parameters, context: ProcessingContext) -> Result<Self>
     where
         Self: Sized,
@@ -86,13 +124,41 @@ impl ProcessingNode for RawDirectoryWriter {
         let current_frame_number = frame_lock.frame();
         let mut file =
             File::create(format!("{}/{:06}.data", &self.dir_path, current_frame_number))?;
-        if let Ok(frame) = self.context.ensure_cpu_buffer::<Rgb>(input) {
-            frame.storage.as_slice(|slice| file.write_all(slice))?;
-        } else if let Ok(frame) = self.context.ensure_cpu_buffer::<Raw>(input) {
-            frame.storage.as_slice(|slice| file.write_all(slice))?;
-        } else {
-            return Err(anyhow!("unknown input format {}", input.type_name));
+        let (storage, metadata) = frame_data(&self.context, input)?;
+        storage.as_slice(|slice| file.write_all(slice))?;
+
+        if !metadata.is_empty() {
+            let metadata_file =
+                File::create(format!("{}/{:06}.json", &self.dir_path, current_frame_number))?;
+            serde_json::to_writer_pretty(metadata_file, &metadata.to_json())?;
         }
         Ok(Some(Payload::empty()))
     }
 }
+
+const ALL_FRAME_TYPES: [PortType; 7] = [
+    PortType::Raw { bit_depth: None },
+    PortType::Rgb,
+    PortType::Rgb16,
+    PortType::Rgba16,
+    PortType::RgbF16,
+    PortType::RgbF32,
+    PortType::Yuv,
+];
+
+// the bytes and the metadata of a frame of any interpretation, as the raw
+// writers dont care about the meaning of the data they write
+fn frame_data(
+    context: &ProcessingContext,
+    input: &mut Payload,
+) -> Result<(CpuBuffer, FrameMetadata)> {
+    macro_rules! try_interpretations {
+        ($($interpretation:ty),*) => {$(
+            if let Ok(frame) = context.ensure_cpu_buffer::<$interpretation>(input) {
+                return Ok((frame.storage.clone(), frame.metadata.clone()));
+            }
+        )*};
+    }
+    try_interpretations!(Raw, Rgb, Rgba, Rgb16, Rgba16, RgbF16, RgbF32, Yuv);
+    Err(anyhow!("unknown input format {}", input.type_name))
+}
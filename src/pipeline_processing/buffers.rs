@@ -1,33 +1,100 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+    RwLock,
+};
 
+#[cfg(feature = "gpu")]
 use vulkano::buffer::{BufferAccess, CpuAccessibleBuffer, TypedBufferAccess};
 
+static ALLOCATED_CPU_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of bytes currently held by all cpu buffers of this process
+pub fn allocated_cpu_bytes() -> usize { ALLOCATED_CPU_BYTES.load(Ordering::Relaxed) }
+
+struct AllocationGuard(usize);
+impl AllocationGuard {
+    fn new(len: usize) -> Self {
+        ALLOCATED_CPU_BYTES.fetch_add(len, Ordering::Relaxed);
+        Self(len)
+    }
+}
+impl Drop for AllocationGuard {
+    fn drop(&mut self) { ALLOCATED_CPU_BYTES.fetch_sub(self.0, Ordering::Relaxed); }
+}
+
+#[derive(Clone)]
+enum CpuBufferStorage {
+    #[cfg(feature = "gpu")]
+    Vulkan(Arc<CpuAccessibleBuffer<[u8]>>),
+    // plain memory for processing without a vulkan device
+    Heap(Arc<RwLock<Box<[u8]>>>),
+}
+
 #[derive(Clone)]
 pub struct CpuBuffer {
-    buf: Arc<CpuAccessibleBuffer<[u8]>>,
+    storage: CpuBufferStorage,
+    _allocation: Arc<AllocationGuard>,
 }
+#[cfg(feature = "gpu")]
 impl From<Arc<CpuAccessibleBuffer<[u8]>>> for CpuBuffer {
-    fn from(buf: Arc<CpuAccessibleBuffer<[u8]>>) -> Self { Self { buf } }
+    fn from(buf: Arc<CpuAccessibleBuffer<[u8]>>) -> Self {
+        let allocation = Arc::new(AllocationGuard::new(buf.len() as _));
+        Self { storage: CpuBufferStorage::Vulkan(buf), _allocation: allocation }
+    }
 }
 impl CpuBuffer {
-    pub fn len(&self) -> usize { self.buf.len() as _ }
+    /// Allocates a zeroed buffer in normal memory, that the gpu cant access
+    /// directly
+    pub fn new_heap(len: usize) -> Self {
+        Self {
+            storage: CpuBufferStorage::Heap(Arc::new(RwLock::new(vec![0; len].into_boxed_slice()))),
+            _allocation: Arc::new(AllocationGuard::new(len)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            #[cfg(feature = "gpu")]
+            CpuBufferStorage::Vulkan(buf) => buf.len() as _,
+            CpuBufferStorage::Heap(buf) => buf.read().unwrap().len(),
+        }
+    }
 
-    pub fn cpu_accessible_buffer(&self) -> Arc<CpuAccessibleBuffer<[u8]>> { self.buf.clone() }
+    /// The underlying vulkan buffer or None if the buffer lives in normal
+    /// memory
+    #[cfg(feature = "gpu")]
+    pub fn cpu_accessible_buffer(&self) -> Option<Arc<CpuAccessibleBuffer<[u8]>>> {
+        match &self.storage {
+            CpuBufferStorage::Vulkan(buf) => Some(buf.clone()),
+            CpuBufferStorage::Heap(_) => None,
+        }
+    }
 
     pub fn as_slice<FN: FnOnce(&[u8]) -> R, R>(&self, func: FN) -> R {
-        func(&*self.buf.read().unwrap())
+        match &self.storage {
+            #[cfg(feature = "gpu")]
+            CpuBufferStorage::Vulkan(buf) => func(&*buf.read().unwrap()),
+            CpuBufferStorage::Heap(buf) => func(&*buf.read().unwrap()),
+        }
     }
 
     pub fn as_mut_slice<FN: FnOnce(&mut [u8]) -> R, R>(&mut self, func: FN) -> R {
-        func(&mut *self.buf.write().unwrap())
+        match &self.storage {
+            #[cfg(feature = "gpu")]
+            CpuBufferStorage::Vulkan(buf) => func(&mut *buf.write().unwrap()),
+            CpuBufferStorage::Heap(buf) => func(&mut *buf.write().unwrap()),
+        }
     }
 }
 
+#[cfg(feature = "gpu")]
 #[derive(Clone)]
 pub struct GpuBuffer {
     typed_buffer_access: Arc<dyn TypedBufferAccess<Content = [u8]> + Send + Sync>,
     buffer_access: Arc<(dyn BufferAccess)>,
 }
+#[cfg(feature = "gpu")]
 impl<T: TypedBufferAccess<Content = [u8]> + Send + Sync + 'static> From<Arc<T>> for GpuBuffer {
     fn from(typed_buffer_acccess: Arc<T>) -> Self {
         Self {
@@ -36,6 +103,7 @@ impl<T: TypedBufferAccess<Content = [u8]> + Send + Sync + 'static> From<Arc<T>>
         }
     }
 }
+#[cfg(feature = "gpu")]
 impl GpuBuffer {
     pub fn typed(&self) -> Arc<dyn TypedBufferAccess<Content = [u8]> + Send + Sync> {
         self.typed_buffer_access.clone()
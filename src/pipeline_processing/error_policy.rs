@@ -0,0 +1,68 @@
+use crate::pipeline_processing::processing_context::ProcessingContext;
+use std::fmt::{self, Display, Formatter};
+
+/// What the executor does, when a node fails to process a frame
+#[derive(Clone, Default)]
+pub enum ErrorPolicy {
+    /// Stop the whole pipeline
+    #[default]
+    Abort,
+    /// Drop the frame for all nodes downstream of the failed node
+    Skip,
+    /// Let the node try again up to this many times, then skip the frame. This
+    /// only helps nodes that give the same result when they process the same
+    /// input again. Sources are never retried, as they would read the next
+    /// frame instead of the failed one, so their failures are skipped.
+    Retry(u32),
+    /// Use the last output of the failed node instead. The frame is skipped if
+    /// there is no previous output.
+    SubstitutePrevious,
+    /// Use a black frame of the same format as the last output of the failed
+    /// node instead. The frame is skipped if there is no previous output.
+    SubstituteBlack(ProcessingContext),
+}
+impl ErrorPolicy {
+    pub fn retries(&self) -> u32 {
+        match self {
+            ErrorPolicy::Retry(retries) => *retries,
+            _ => 0,
+        }
+    }
+
+    pub fn needs_last_output(&self) -> bool {
+        matches!(self, ErrorPolicy::SubstitutePrevious | ErrorPolicy::SubstituteBlack(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRecovery {
+    Skipped,
+    /// The node succeeded after this many retries
+    Retried(u32),
+    SubstitutedPrevious,
+    SubstitutedBlack,
+}
+impl Display for FrameRecovery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameRecovery::Skipped => write!(f, "skipped the frame"),
+            FrameRecovery::Retried(retries) => write!(f, "succeeded after {} retries", retries),
+            FrameRecovery::SubstitutedPrevious => write!(f, "used the previous frame instead"),
+            FrameRecovery::SubstitutedBlack => write!(f, "used a black frame instead"),
+        }
+    }
+}
+
+/// A frame that failed in a node, but did not stop the pipeline
+#[derive(Debug, Clone)]
+pub struct FrameFailure {
+    pub frame: u64,
+    pub node: String,
+    pub error: String,
+    pub recovery: FrameRecovery,
+}
+impl Display for FrameFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "frame {} in node {}: {} ({})", self.frame, self.node, self.error, self.recovery)
+    }
+}
@@ -1,55 +1,541 @@
-use crate::pipeline_processing::{payload::Payload, processing_node::ProcessingNode};
-use anyhow::Result;
+use crate::pipeline_processing::{
+    buffers::allocated_cpu_bytes,
+    error_policy::{ErrorPolicy, FrameFailure, FrameRecovery},
+    graph::{NodeId, ProcessingGraph},
+    payload::Payload,
+    progress::{ExecutionState, NodeState, Progress},
+};
+use anyhow::{anyhow, Context, Result};
 use itertools::Itertools;
 use rayon::prelude::*;
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
-    Condvar,
-    Mutex,
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Condvar,
+        Mutex,
+        Once,
+    },
+    thread,
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-pub fn execute_pipeline(nodes: Vec<Arc<dyn ProcessingNode>>) -> Result<()> {
-    let nodes = Arc::new(nodes);
-    let progress =
-        Arc::new((0..nodes.len()).map(|_| ProcessingStageLock::new()).collect::<Vec<_>>());
-    let frame = AtomicU64::new(1);
-
-    let result = rayon::iter::repeat(0)
-        .into_par_iter()
-        .map(|_| {
-            let frame = frame.fetch_add(1, Ordering::SeqCst);
-            let mut payload = Payload::empty();
-            for (node_num, node) in nodes.iter().enumerate() {
-                // emits a waiter for the previous frame
-
-                match node.process(&mut payload, progress[node_num].waiter_for(frame - 1)) {
-                    Ok(Some(new_payload)) => payload = new_payload,
-                    Ok(None) => {
-                        return Some(Ok(()));
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct ExecutionOptions {
+    /// The maximum number of frames that are processed at the same time.
+    /// Defaults to the number of worker threads.
+    pub max_frames_in_flight: Option<usize>,
+    /// No new frames are started while the cpu buffers of the process take up
+    /// more than this many bytes.
+    pub memory_budget: Option<usize>,
+    /// Stop reading new frames on SIGINT / SIGTERM during this run. The frames
+    /// in flight are still processed to the end.
+    pub stop_on_signal: bool,
+    /// Gets called after every frame that went through the whole pipeline.
+    /// This happens on the worker threads, so it should return quickly.
+    pub progress_callback: Option<ProgressCallback>,
+    /// What happens with frames that fail in a node
+    pub error_policy: ErrorPolicy,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionSummary {
+    /// The number of frames that went through the whole pipeline
+    pub frames: u64,
+    pub duration: Duration,
+    /// Whether the pipeline was stopped by a signal or cancelled instead of
+    /// running out of frames
+    pub interrupted: bool,
+    /// The frames that failed but were recovered by the error policy
+    pub failed_frames: Vec<FrameFailure>,
+}
+
+// even with a lenient error policy the pipeline is stopped, if this many frames
+// in a row fail. otherwise a broken source would fail forever
+const MAX_CONSECUTIVE_FAILURES: u64 = 100;
+
+// the running pipelines that stop on SIGINT / SIGTERM, together with whether
+// they already got a signal
+static SIGNAL_LISTENERS: Mutex<Vec<(PipelineCanceller, bool)>> = Mutex::new(Vec::new());
+
+fn install_signal_handler() -> Result<()> {
+    static INSTALL_SIGNAL_HANDLER: Once = Once::new();
+    let mut result = Ok(());
+    INSTALL_SIGNAL_HANDLER.call_once(|| result = ctrlc::set_handler(on_signal));
+    Ok(result?)
+}
+
+fn on_signal() {
+    let mut listeners = SIGNAL_LISTENERS.lock().unwrap();
+    if listeners.is_empty() {
+        std::process::exit(130);
+    }
+    if listeners.iter().any(|(_, signalled)| *signalled) {
+        eprintln!("\nreceived second signal, exiting immediately");
+        std::process::exit(130);
+    }
+    eprintln!("\nreceived signal, finishing the frames in flight (repeat to force exit)");
+    for (canceller, signalled) in listeners.iter_mut() {
+        canceller.cancel();
+        *signalled = true;
+    }
+}
+
+// cancels the pipeline on a signal as long as it is alive
+struct SignalListener(PipelineCanceller);
+impl SignalListener {
+    fn register(state: &Arc<ExecutionState>) -> Result<Self> {
+        install_signal_handler()?;
+        let canceller = PipelineCanceller(state.clone());
+        SIGNAL_LISTENERS.lock().unwrap().push((canceller.clone(), false));
+        Ok(SignalListener(canceller))
+    }
+}
+impl Drop for SignalListener {
+    fn drop(&mut self) {
+        SIGNAL_LISTENERS
+            .lock()
+            .unwrap()
+            .retain(|(canceller, _)| !Arc::ptr_eq(&canceller.0, &(self.0).0));
+    }
+}
+
+/// A pipeline that is running on a background thread
+pub struct PipelineHandle {
+    state: Arc<ExecutionState>,
+    join_handle: JoinHandle<Result<ExecutionSummary>>,
+}
+impl PipelineHandle {
+    pub fn progress(&self) -> Progress { self.state.progress() }
+
+    /// Stops pulling new frames from the source. The frames in flight are
+    /// still processed and all nodes are finished as usual.
+    pub fn cancel(&self) { self.state.cancel() }
+
+    /// Returns a handle that can cancel the pipeline from other threads
+    pub fn canceller(&self) -> PipelineCanceller { PipelineCanceller(self.state.clone()) }
+
+    pub fn is_finished(&self) -> bool { self.join_handle.is_finished() }
+
+    /// Blocks until the pipeline has finished and returns its result
+    pub fn join(self) -> Result<ExecutionSummary> {
+        self.join_handle.join().map_err(|_| anyhow!("the pipeline thread panicked"))?
+    }
+}
+
+#[derive(Clone)]
+pub struct PipelineCanceller(Arc<ExecutionState>);
+impl PipelineCanceller {
+    pub fn cancel(&self) { self.0.cancel() }
+}
+
+/// Starts the pipeline on a background thread
+pub fn start_pipeline(graph: ProcessingGraph, options: ExecutionOptions) -> Result<PipelineHandle> {
+    let state = Arc::new(ExecutionState::new(&graph));
+    let thread_state = state.clone();
+    let join_handle = thread::Builder::new()
+        .name("pipeline".to_string())
+        .spawn(move || execute(graph, options, &thread_state))?;
+    Ok(PipelineHandle { state, join_handle })
+}
+
+/// Runs the pipeline on the current thread until the source runs out of
+/// frames, a node fails or the pipeline is stopped by a signal
+pub fn execute_pipeline(
+    graph: ProcessingGraph,
+    options: ExecutionOptions,
+) -> Result<ExecutionSummary> {
+    let state = Arc::new(ExecutionState::new(&graph));
+    execute(graph, options, &state)
+}
+
+fn execute(
+    graph: ProcessingGraph,
+    options: ExecutionOptions,
+    state: &Arc<ExecutionState>,
+) -> Result<ExecutionSummary> {
+    // signals only stop the pipelines that are running while they arrive
+    let _signal_listener =
+        if options.stop_on_signal { Some(SignalListener::register(state)?) } else { None };
+    start_nodes(&graph, state)?;
+
+    let execution = Execution::new(&graph, state, &options.error_policy);
+    let in_flight_limiter = InFlightLimiter::new(&options);
+    let stop = Mutex::new(None);
+    let mut interrupted = false;
+
+    // the frames are started from the current thread and not from inside the thread
+    // pool: a worker that waits for a free slot could otherwise be stacked on top
+    // of an older frame (for example while it joins the branches of that
+    // frame), whose slot would then never be freed
+    rayon::in_place_scope(|scope| {
+        let (graph, execution, stop, options) = (&graph, &execution, &stop, &options);
+        let mut frame = 1;
+        loop {
+            // the frame number is only taken after we got a slot, so all older frames
+            // are already being processed and we can never wait for a frame that has
+            // not started
+            let queued_since = Instant::now();
+            let permit = in_flight_limiter.acquire();
+            if stop.lock().unwrap().is_some() {
+                break;
+            }
+            if state.is_cancelled() {
+                interrupted = true;
+                break;
+            }
+
+            let current_frame = frame;
+            frame += 1;
+            scope.spawn(move |_| {
+                // the slot is freed after the outcome is recorded, so the loop above
+                // sees it when it gets the next slot
+                let _permit = permit;
+                match execution.process_node(
+                    graph.source(),
+                    Payload::empty(),
+                    current_frame,
+                    queued_since,
+                ) {
+                    FrameOutcome::Done => {
+                        execution.consecutive_failures.store(0, Ordering::SeqCst);
+                        state.frame_done();
+                        if let Some(callback) = &options.progress_callback {
+                            callback(&state.progress());
+                        }
                     }
-                    Err(e) => {
-                        eprintln!(
-                            "An error occured: \n{}",
-                            e.chain().map(|e| format!("{}", e)).join("\n")
-                        );
-                        return Some(Err(e));
+                    FrameOutcome::Skipped => {}
+                    FrameOutcome::Stop(result) => {
+                        let mut stop = stop.lock().unwrap();
+                        // an error is more interesting than the end of the stream
+                        if stop.as_ref().is_none_or(|stop: &Result<()>| stop.is_ok()) {
+                            *stop = Some(result);
+                        }
                     }
                 }
-                progress[node_num].process(frame);
+            });
+        }
+    });
+    // the nodes are finalized even if processing failed, but that error is the more
+    // interesting one
+    let finish_result = finish_nodes(&graph, state, graph.len());
+    stop.into_inner().unwrap().unwrap_or(Ok(()))?;
+    finish_result?;
+
+    Ok(ExecutionSummary {
+        frames: state.frames_done(),
+        duration: state.elapsed(),
+        interrupted,
+        failed_frames: state.failures(),
+    })
+}
+
+fn start_nodes(graph: &ProcessingGraph, state: &ExecutionState) -> Result<()> {
+    for (id, node) in graph.iter() {
+        if let Err(e) = node.node.start() {
+            state.set_node_state(id, NodeState::Failed);
+            // the nodes that were started already are cleaned up again
+            if let Err(finish_error) = finish_nodes(graph, state, id) {
+                print_error(&finish_error);
+            }
+            return Err(e.context(format!("error while starting node {}", node.name)));
+        }
+        state.set_node_state(id, NodeState::Running);
+    }
+    Ok(())
+}
+
+// flushes and then finishes the first `count` nodes (in topological order). all
+// nodes are finished, even if some fail. the first error is returned, the other
+// ones are only printed
+fn finish_nodes(graph: &ProcessingGraph, state: &ExecutionState, count: usize) -> Result<()> {
+    let mut result = Ok(());
+    let mut record_error = |id: NodeId, node_result: Result<()>| match node_result {
+        Ok(()) => {}
+        Err(e) => {
+            state.set_node_state(id, NodeState::Failed);
+            if result.is_ok() {
+                result = Err(e)
+            } else {
+                print_error(&e)
+            }
+        }
+    };
+
+    for (id, node) in graph.iter().take(count) {
+        state.set_node_state(id, NodeState::Finishing);
+        record_error(
+            id,
+            node.node.flush().with_context(|| format!("error while flushing node {}", node.name)),
+        );
+    }
+    for (id, node) in graph.iter().take(count) {
+        match node.node.finish() {
+            Ok(()) => state.set_node_state(id, NodeState::Finished),
+            Err(e) => record_error(
+                id,
+                Err(e.context(format!("error while finishing node {}", node.name))),
+            ),
+        }
+    }
+    result
+}
+
+fn print_error(e: &anyhow::Error) {
+    eprintln!("An error occured: \n{}", e.chain().map(|e| format!("{}", e)).join("\n"));
+}
+
+struct InFlightLimiter {
+    condvar: Condvar,
+    in_flight: Mutex<usize>,
+    max_frames: usize,
+    memory_budget: Option<usize>,
+}
+struct InFlightPermit<'a> {
+    limiter: &'a InFlightLimiter,
+}
+impl InFlightLimiter {
+    fn new(options: &ExecutionOptions) -> Self {
+        InFlightLimiter {
+            condvar: Condvar::new(),
+            in_flight: Mutex::new(0),
+            max_frames: options.max_frames_in_flight.unwrap_or_else(rayon::current_num_threads),
+            memory_budget: options.memory_budget,
+        }
+    }
+
+    fn has_room(&self, in_flight: usize) -> bool {
+        // a single frame is always allowed, otherwise nothing would ever free memory
+        in_flight == 0
+            || (in_flight < self.max_frames
+                && self.memory_budget.is_none_or(|budget| allocated_cpu_bytes() < budget))
+    }
+
+    // blocks until a new frame may be started. this must not be called from a
+    // worker of the thread pool, see `execute`
+    fn acquire(&self) -> InFlightPermit<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while !self.has_room(*in_flight) {
+            // memory can also be freed outside of the pipeline (for example by the display
+            // thread), so we cant rely on being notified
+            in_flight = self.condvar.wait_timeout(in_flight, Duration::from_millis(10)).unwrap().0;
+        }
+        *in_flight += 1;
+        InFlightPermit { limiter: self }
+    }
+}
+impl<'a> Drop for InFlightPermit<'a> {
+    fn drop(&mut self) {
+        *self.limiter.in_flight.lock().unwrap() -= 1;
+        self.limiter.condvar.notify_all();
+    }
+}
+
+// what happened to a frame in a part of the graph
+enum FrameOutcome {
+    Done,
+    /// The frame was dropped in at least one branch
+    Skipped,
+    /// The pipeline should stop
+    Stop(Result<()>),
+}
+impl FrameOutcome {
+    // combines the outcomes of two branches
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (FrameOutcome::Stop(result), _) | (_, FrameOutcome::Stop(result)) => {
+                FrameOutcome::Stop(result)
+            }
+            (FrameOutcome::Skipped, _) | (_, FrameOutcome::Skipped) => FrameOutcome::Skipped,
+            _ => FrameOutcome::Done,
+        }
+    }
+}
+
+// the state of a single run that is needed while processing frames
+struct Execution<'a> {
+    graph: &'a ProcessingGraph,
+    state: &'a ExecutionState,
+    error_policy: &'a ErrorPolicy,
+    // the newest output of every node together with its frame number. only
+    // filled if the error policy substitutes failed frames
+    last_outputs: Vec<Mutex<Option<(u64, Payload)>>>,
+    consecutive_failures: AtomicU64,
+}
+
+impl<'a> Execution<'a> {
+    fn new(
+        graph: &'a ProcessingGraph,
+        state: &'a ExecutionState,
+        error_policy: &'a ErrorPolicy,
+    ) -> Self {
+        Execution {
+            graph,
+            state,
+            error_policy,
+            last_outputs: (0..graph.len()).map(|_| Mutex::new(None)).collect(),
+            consecutive_failures: AtomicU64::new(0),
+        }
+    }
+
+    // processes a single frame in the given node and (recursively) in all nodes
+    // downstream of it. `queued_since` is the time the input became available
+    fn process_node(
+        &self,
+        node_id: NodeId,
+        mut input: Payload,
+        frame: u64,
+        queued_since: Instant,
+    ) -> FrameOutcome {
+        let node = self.graph.get(node_id);
+        self.state.node_queued(node_id, queued_since.elapsed());
+
+        // sources would read the next frame instead of trying the failed one again
+        let max_retries =
+            if node_id == self.graph.source() { 0 } else { self.error_policy.retries() };
+        let mut retries = 0;
+        let mut last_error = None;
+        let result = loop {
+            // emits a waiter for the previous frame
+            self.state.node_process_started(node_id);
+            let start_time = Instant::now();
+            let result =
+                node.node.process(&mut input, self.state.stage_lock(node_id).waiter_for(frame - 1));
+            self.state.node_process_ended(
+                node_id,
+                matches!(result, Ok(Some(_))),
+                start_time.elapsed(),
+            );
+            match result {
+                Err(e) if retries < max_retries => {
+                    retries += 1;
+                    last_error = Some(e);
+                }
+                result => break result,
+            }
+        };
+
+        let output = match result {
+            Ok(Some(output)) => {
+                if let Some(e) = last_error {
+                    self.record_failure(node_id, frame, &e, FrameRecovery::Retried(retries));
+                }
+                output
+            }
+            Ok(None) => {
+                self.release_stage_locks(node_id, frame);
+                return FrameOutcome::Stop(Ok(()));
+            }
+            Err(e) => match self.recover(node_id, frame, e) {
+                Ok(Some(substitute)) => substitute,
+                Ok(None) => {
+                    self.release_stage_locks(node_id, frame);
+                    return FrameOutcome::Skipped;
+                }
+                Err(e) => {
+                    self.state.set_node_state(node_id, NodeState::Failed);
+                    print_error(&e);
+                    self.release_stage_locks(node_id, frame);
+                    return FrameOutcome::Stop(Err(e));
+                }
+            },
+        };
+
+        if self.error_policy.needs_last_output() {
+            let mut last_output = self.last_outputs[node_id].lock().unwrap();
+            if last_output.as_ref().is_none_or(|(last_frame, _)| *last_frame < frame) {
+                *last_output = Some((frame, output.clone()));
             }
+        }
+        self.state.stage_lock(node_id).process(frame);
+
+        // all branches get to see the frame, even if one of them wants to stop
+        let output_time = Instant::now();
+        self.graph
+            .children(node_id)
+            .into_par_iter()
+            .map(|child| self.process_node(child, output.clone(), frame, output_time))
+            .reduce(|| FrameOutcome::Done, FrameOutcome::merge)
+    }
+
+    // applies the error policy to a frame that failed in a node. returns the
+    // payload to continue with, None to skip the frame or an error to stop the
+    // pipeline
+    fn recover(
+        &self,
+        node_id: NodeId,
+        frame: u64,
+        error: anyhow::Error,
+    ) -> Result<Option<Payload>> {
+        let with_context = |error: anyhow::Error| {
+            error.context(format!(
+                "error in node {} at frame {}",
+                self.graph.get(node_id).name,
+                frame
+            ))
+        };
+        let last_output = || self.last_outputs[node_id].lock().unwrap().clone().map(|(_, p)| p);
+        let substitute = match self.error_policy {
+            ErrorPolicy::Abort => return Err(with_context(error)),
+            ErrorPolicy::Skip | ErrorPolicy::Retry(_) => None,
+            ErrorPolicy::SubstitutePrevious => {
+                last_output().map(|payload| (payload, FrameRecovery::SubstitutedPrevious))
+            }
+            ErrorPolicy::SubstituteBlack(context) => last_output()
+                .map(|payload| context.black_frame_like(&payload))
+                .transpose()?
+                .map(|payload| (payload, FrameRecovery::SubstitutedBlack)),
+        };
+        let recovery =
+            substitute.as_ref().map_or(FrameRecovery::Skipped, |(_, recovery)| *recovery);
+        self.record_failure(node_id, frame, &error, recovery);
+
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= MAX_CONSECUTIVE_FAILURES {
+            return Err(with_context(error)
+                .context(format!("giving up after {} failed frames in a row", failures)));
+        }
+        Ok(substitute.map(|(payload, _)| payload))
+    }
 
-            None
-        })
-        .find_any(|result| result.is_some())
-        .unwrap();
-    result.unwrap()
+    fn record_failure(
+        &self,
+        node_id: NodeId,
+        frame: u64,
+        error: &anyhow::Error,
+        recovery: FrameRecovery,
+    ) {
+        let failure = FrameFailure {
+            frame,
+            node: self.graph.get(node_id).name.clone(),
+            error: error.chain().map(|e| format!("{}", e)).join(": "),
+            recovery,
+        };
+        self.state.record_failure(failure);
+    }
+
+    // marks a frame that did not reach the given node and all nodes downstream of
+    // it as done, so that later frames dont wait for it forever. the previous frame
+    // is waited for first, so later frames still cant overtake it. this waiting is
+    // not counted as waiting time of the nodes
+    fn release_stage_locks(&self, node_id: NodeId, frame: u64) {
+        self.state.stage_lock(node_id).wait_until(frame - 1);
+        self.state.stage_lock(node_id).process(frame);
+        for child in self.graph.children(node_id) {
+            self.release_stage_locks(child, frame);
+        }
+    }
 }
 
 pub struct ProcessingStageLock {
     condvar: Condvar,
     // hold the frame currently done
     val: Mutex<u64>,
+    // the total time spent in `ProcessingStageLockWaiter::wait` in nanoseconds
+    time_waited: AtomicU64,
 }
 
 pub struct ProcessingStageLockWaiter<'a> {
@@ -61,23 +547,127 @@ impl<'a> ProcessingStageLockWaiter<'a> {
     pub fn frame(&self) -> u64 { self.frame + 1 }
 
     pub fn wait(&self) {
-        drop(
-            self.lock
-                .condvar
-                .wait_while(self.lock.val.lock().unwrap(), |v| *v < self.frame)
-                .unwrap(),
-        )
+        let start_time = Instant::now();
+        self.lock.wait_until(self.frame);
+        self.lock.time_waited.fetch_add(start_time.elapsed().as_nanos() as u64, Ordering::Relaxed);
     }
 }
 
+impl Default for ProcessingStageLock {
+    fn default() -> Self { Self::new() }
+}
 impl ProcessingStageLock {
-    pub fn new() -> Self { ProcessingStageLock { condvar: Condvar::new(), val: Mutex::new(0) } }
+    pub fn new() -> Self {
+        ProcessingStageLock {
+            condvar: Condvar::new(),
+            val: Mutex::new(0),
+            time_waited: AtomicU64::new(0),
+        }
+    }
     pub fn waiter_for<'a>(&'a self, val: u64) -> ProcessingStageLockWaiter<'a> {
         ProcessingStageLockWaiter { lock: self, frame: val }
     }
+    // blocks until `val` was processed
+    fn wait_until(&self, val: u64) {
+        drop(self.condvar.wait_while(self.val.lock().unwrap(), |v| *v < val).unwrap());
+    }
     pub fn process(&self, val: u64) {
         let mut locked = self.val.lock().unwrap();
         *locked = locked.max(val);
         self.condvar.notify_all();
     }
+    /// The total time all waiters of this lock were blocked
+    pub fn time_waited(&self) -> Duration {
+        Duration::from_nanos(self.time_waited.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline_processing::{
+        execute::{
+            execute_pipeline,
+            on_signal,
+            ExecutionOptions,
+            ProcessingStageLock,
+            ProcessingStageLockWaiter,
+        },
+        graph::ProcessingGraph,
+        payload::Payload,
+        processing_node::ProcessingNode,
+    };
+    use anyhow::Result;
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    // a source with the given number of frames, which blocks in the first
+    // frame until `signal` was called
+    struct Frames {
+        left: AtomicU64,
+        signal: Option<fn()>,
+    }
+    impl ProcessingNode for Frames {
+        fn process(
+            &self,
+            _input: &mut Payload,
+            _frame_lock: ProcessingStageLockWaiter,
+        ) -> Result<Option<Payload>> {
+            if let Some(signal) = self.signal {
+                if self.left.load(Ordering::SeqCst) == 3 {
+                    signal();
+                }
+            }
+            let left = self
+                .left
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1));
+            Ok(left.ok().map(|_| Payload::empty()))
+        }
+    }
+
+    fn run(signal: Option<fn()>) -> (u64, bool) {
+        let source = Arc::new(Frames { left: AtomicU64::new(3), signal });
+        let graph = ProcessingGraph::new("Frames", source as Arc<dyn ProcessingNode>);
+        let options = ExecutionOptions {
+            max_frames_in_flight: Some(1),
+            stop_on_signal: true,
+            ..ExecutionOptions::default()
+        };
+        let summary = execute_pipeline(graph, options).unwrap();
+        (summary.frames, summary.interrupted)
+    }
+
+    #[test]
+    fn test_signal_only_stops_running_pipelines() {
+        // the first frame is still finished
+        assert_eq!(run(Some(on_signal)), (1, true));
+        assert_eq!(run(None), (3, false));
+    }
+
+    #[test]
+    fn test_only_waiters_count_as_waiting() {
+        let lock = ProcessingStageLock::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                lock.process(1);
+            });
+            lock.wait_until(1);
+        });
+        assert_eq!(lock.time_waited(), Duration::ZERO);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                lock.process(2);
+            });
+            lock.waiter_for(2).wait();
+        });
+        assert!(lock.time_waited() >= Duration::from_millis(20));
+    }
 }
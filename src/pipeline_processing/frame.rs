@@ -1,3 +1,11 @@
+use crate::pipeline_processing::{metadata::FrameMetadata, payload::Payload, port_type::PortType};
+use anyhow::{anyhow, Result};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+
 pub trait FrameInterpretation {
     fn required_bytes(&self) -> usize;
 }
@@ -7,17 +15,433 @@ pub trait FrameInterpretation {
 pub struct Frame<Interpretation, Storage> {
     pub interp: Interpretation,
     pub storage: Storage,
+    pub metadata: FrameMetadata,
+}
+
+/// The largest width and height of a repeating color filter pattern
+pub const MAX_CFA_SIZE: usize = 8;
+
+/// The color of a single filter of a color filter array. The values are the
+/// indices of the color channel (and the codes dng uses).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CfaColor {
+    Red = 0,
+    Green = 1,
+    Blue = 2,
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct CfaDescriptor {
-    pub first_is_red_x: bool,
-    pub first_is_red_y: bool,
+/// The color filter array of a sensor: a pattern of color filters that repeats
+/// over the whole frame, starting at the top left pixel. As a string, patterns
+/// are written row by row, like `RG/GB` for bayer or `RRGG/RRGG/GGBB/GGBB` for
+/// quad bayer. 2x2 patterns can also be written as `RGGB`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CfaDescriptor {
+    /// a sensor without color filters
+    Mono,
+    Pattern {
+        width: u8,
+        height: u8,
+        colors: [CfaColor; MAX_CFA_SIZE * MAX_CFA_SIZE],
+    },
 }
 
 impl CfaDescriptor {
+    pub fn from_rows(rows: &[Vec<CfaColor>]) -> Result<Self> {
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        if !(1..=MAX_CFA_SIZE).contains(&width) || !(1..=MAX_CFA_SIZE).contains(&height) {
+            return Err(anyhow!(
+                "a cfa pattern has to be between 1x1 and {0}x{0}, but is {1}x{2}",
+                MAX_CFA_SIZE,
+                width,
+                height
+            ));
+        }
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(anyhow!("all rows of a cfa pattern need to have the same length"));
+        }
+
+        let mut colors = [CfaColor::Green; MAX_CFA_SIZE * MAX_CFA_SIZE];
+        for (i, color) in rows.iter().flatten().enumerate() {
+            colors[i] = *color;
+        }
+        Ok(CfaDescriptor::Pattern { width: width as u8, height: height as u8, colors })
+    }
+
+    /// A bayer pattern. The old way of describing the four possible bayer
+    /// patterns: whether the first pixel in x / y direction is red.
     pub fn from_first_red(first_is_red_x: bool, first_is_red_y: bool) -> Self {
-        CfaDescriptor { first_is_red_x, first_is_red_y }
+        let bayer = CfaDescriptor::from_rows(&[
+            vec![CfaColor::Red, CfaColor::Green],
+            vec![CfaColor::Green, CfaColor::Blue],
+        ])
+        .unwrap();
+        bayer.shifted(!first_is_red_x as u64, !first_is_red_y as u64)
+    }
+
+    /// The size of the repeating pattern as (width, height). Mono sensors have
+    /// a 1x1 pattern.
+    pub fn size(&self) -> (u64, u64) {
+        match self {
+            CfaDescriptor::Mono => (1, 1),
+            CfaDescriptor::Pattern { width, height, .. } => (*width as u64, *height as u64),
+        }
+    }
+
+    /// The color of the filter in front of the pixel at `x`, `y` or `None` for
+    /// mono sensors
+    pub fn color_at(&self, x: u64, y: u64) -> Option<CfaColor> {
+        match self {
+            CfaDescriptor::Mono => None,
+            CfaDescriptor::Pattern { width, height, colors } => {
+                let (width, height) = (*width as u64, *height as u64);
+                Some(colors[((y % height) * width + x % width) as usize])
+            }
+        }
+    }
+
+    /// Whether this is one of the four 2x2 bayer patterns, with green on one
+    /// diagonal and red and blue on the other
+    pub fn is_bayer(&self) -> bool {
+        if self.size() != (2, 2) {
+            return false;
+        }
+        let color = |x, y| self.color_at(x, y).unwrap();
+        let (green_x, other_x) = if color(0, 0) == CfaColor::Green { (0, 1) } else { (1, 0) };
+        color(green_x, 0) == CfaColor::Green
+            && color(other_x, 1) == CfaColor::Green
+            && color(other_x, 0) != CfaColor::Green
+            && color(green_x, 1) != CfaColor::Green
+            && color(other_x, 0) != color(green_x, 1)
+    }
+
+    /// The pattern of a frame that is cropped by `x` and `y` pixels at the top
+    /// left
+    pub fn shifted(self, x: u64, y: u64) -> Self {
+        match self {
+            CfaDescriptor::Mono => CfaDescriptor::Mono,
+            CfaDescriptor::Pattern { width, height, .. } => {
+                let mut colors = [CfaColor::Green; MAX_CFA_SIZE * MAX_CFA_SIZE];
+                for row in 0..height as u64 {
+                    for column in 0..width as u64 {
+                        colors[(row * width as u64 + column) as usize] =
+                            self.color_at(column + x, row + y).unwrap();
+                    }
+                }
+                CfaDescriptor::Pattern { width, height, colors }
+            }
+        }
+    }
+}
+
+impl FromStr for CfaDescriptor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("mono") {
+            return Ok(CfaDescriptor::Mono);
+        }
+        let rows = if s.chars().count() == 4 && !s.contains('/') {
+            let (second_row, _) = s.char_indices().nth(2).unwrap();
+            vec![&s[..second_row], &s[second_row..]]
+        } else {
+            s.split('/').collect()
+        };
+        let rows = rows
+            .iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c.to_ascii_uppercase() {
+                        'R' => Ok(CfaColor::Red),
+                        'G' => Ok(CfaColor::Green),
+                        'B' => Ok(CfaColor::Blue),
+                        _ => Err(anyhow!("unknown cfa color {} in {}, expected R, G or B", c, s)),
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        CfaDescriptor::from_rows(&rows)
+    }
+}
+
+impl Display for CfaDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (width, height) = match self {
+            CfaDescriptor::Mono => return write!(f, "mono"),
+            CfaDescriptor::Pattern { width, height, .. } => (*width as u64, *height as u64),
+        };
+        for y in 0..height {
+            if y != 0 && (width, height) != (2, 2) {
+                write!(f, "/")?;
+            }
+            for x in 0..width {
+                match self.color_at(x, y) {
+                    Some(CfaColor::Red) => write!(f, "R")?,
+                    Some(CfaColor::Green) => write!(f, "G")?,
+                    Some(CfaColor::Blue) => write!(f, "B")?,
+                    None => unreachable!(),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the samples of a raw frame are laid out in memory. The tight packings
+/// put the samples right after each other without padding, so for example a 12
+/// bit frame takes 3 bytes for every 2 pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitPacking {
+    /// tightly packed starting with the most significant bit of every byte.
+    /// This is what the AXIOM cameras produce.
+    MsbFirst,
+    /// tightly packed starting with the least significant bit of every byte
+    LsbFirst,
+    /// every sample in the low bits of its own 16 bit little endian word
+    U16Le,
+}
+
+impl BitPacking {
+    /// How many bits every sample takes up in memory
+    pub fn bits_per_sample(self, bit_depth: u64) -> u64 {
+        match self {
+            BitPacking::U16Le => 16,
+            BitPacking::MsbFirst | BitPacking::LsbFirst => bit_depth,
+        }
+    }
+
+    pub fn required_bytes(self, bit_depth: u64, samples: usize) -> usize {
+        match self {
+            BitPacking::U16Le => samples * 2,
+            BitPacking::MsbFirst | BitPacking::LsbFirst => (samples * bit_depth as usize + 7) / 8,
+        }
+    }
+
+    /// Calls `func` with every sample of the packed `data` in order
+    pub fn read_samples(self, bit_depth: u64, data: &[u8], mut func: impl FnMut(u16)) {
+        let bit_depth = bit_depth as u32;
+        let mask = (1u32 << bit_depth) - 1;
+        let mut acc = 0u32;
+        let mut bits = 0;
+        match self {
+            BitPacking::U16Le => {
+                for word in data.chunks_exact(2) {
+                    func(u16::from_le_bytes([word[0], word[1]]))
+                }
+            }
+            BitPacking::MsbFirst => {
+                for &byte in data {
+                    acc = (acc << 8) | byte as u32;
+                    bits += 8;
+                    while bits >= bit_depth {
+                        bits -= bit_depth;
+                        func(((acc >> bits) & mask) as u16);
+                    }
+                    acc &= (1 << bits) - 1;
+                }
+            }
+            BitPacking::LsbFirst => {
+                for &byte in data {
+                    acc |= (byte as u32) << bits;
+                    bits += 8;
+                    while bits >= bit_depth {
+                        func((acc & mask) as u16);
+                        acc >>= bit_depth;
+                        bits -= bit_depth;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Packs `samples` into `data`, which has to be `required_bytes` long
+    pub fn write_samples(
+        self,
+        bit_depth: u64,
+        samples: impl IntoIterator<Item = u16>,
+        data: &mut [u8],
+    ) {
+        let bit_depth = bit_depth as u32;
+        let mut acc = 0u32;
+        let mut bits = 0;
+        let mut pos = 0;
+        match self {
+            BitPacking::U16Le => {
+                for (sample, word) in samples.into_iter().zip(data.chunks_exact_mut(2)) {
+                    word.copy_from_slice(&sample.to_le_bytes());
+                }
+            }
+            BitPacking::MsbFirst => {
+                for sample in samples {
+                    acc = (acc << bit_depth) | sample as u32;
+                    bits += bit_depth;
+                    while bits >= 8 {
+                        bits -= 8;
+                        data[pos] = (acc >> bits) as u8;
+                        pos += 1;
+                    }
+                    acc &= (1 << bits) - 1;
+                }
+                if bits > 0 {
+                    data[pos] = (acc << (8 - bits)) as u8;
+                }
+            }
+            BitPacking::LsbFirst => {
+                for sample in samples {
+                    acc |= (sample as u32) << bits;
+                    bits += bit_depth;
+                    while bits >= 8 {
+                        data[pos] = acc as u8;
+                        pos += 1;
+                        acc >>= 8;
+                        bits -= 8;
+                    }
+                }
+                if bits > 0 {
+                    data[pos] = acc as u8;
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for BitPacking {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "msb-first" => Ok(BitPacking::MsbFirst),
+            "lsb-first" => Ok(BitPacking::LsbFirst),
+            "u16le" => Ok(BitPacking::U16Le),
+            _ => Err(anyhow!("unknown bit packing {}, expected msb-first, lsb-first or u16le", s)),
+        }
+    }
+}
+
+impl Display for BitPacking {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BitPacking::MsbFirst => write!(f, "msb-first"),
+            BitPacking::LsbFirst => write!(f, "lsb-first"),
+            BitPacking::U16Le => write!(f, "u16le"),
+        }
+    }
+}
+
+/// An exact frame rate in frames per second, like 24000/1001. It is always
+/// stored as a reduced fraction, so equal rates compare equal. Numerator and
+/// denominator fit into an `i32`, as dng and gstreamer store them signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    numerator: u32,
+    denominator: u32,
+}
+
+impl FrameRate {
+    pub fn new(numerator: u32, denominator: u32) -> Result<Self> {
+        if numerator == 0 || denominator == 0 {
+            return Err(anyhow!("{}/{} is not a valid frame rate", numerator, denominator));
+        }
+        fn gcd(a: u32, b: u32) -> u32 {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+        let divisor = gcd(numerator, denominator);
+        let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+        if numerator > i32::MAX as u32 || denominator > i32::MAX as u32 {
+            return Err(anyhow!(
+                "the frame rate {}/{} is too large, numerator and denominator can be at most {}",
+                numerator,
+                denominator,
+                i32::MAX
+            ));
+        }
+        Ok(FrameRate { numerator, denominator })
+    }
+
+    pub fn numerator(&self) -> u32 { self.numerator }
+    pub fn denominator(&self) -> u32 { self.denominator }
+
+    pub fn as_f64(&self) -> f64 { self.numerator as f64 / self.denominator as f64 }
+
+    /// The whole number of frames per second timecodes count with, like 24 for
+    /// 24000/1001. Rates below one frame per second count with 1.
+    pub fn nominal(&self) -> u32 {
+        let nominal =
+            (self.numerator as u64 + self.denominator as u64 / 2) / self.denominator as u64;
+        nominal.max(1) as u32
+    }
+
+    pub fn frame_duration(&self) -> Duration { self.time_of_frame(1) }
+
+    /// The time at which the frame with the given (zero based) number starts.
+    /// This is computed from the fraction, so it doesnt accumulate rounding
+    /// errors over long recordings.
+    pub fn time_of_frame(&self, frame: u64) -> Duration {
+        let nanos =
+            frame as u128 * self.denominator as u128 * 1_000_000_000 / self.numerator as u128;
+        Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+    }
+}
+
+// the usual ways to write ntsc rates as decimals with their exact numerator
+// over 1001
+const NTSC_RATES: [(&str, u32); 6] = [
+    ("23.976", 24000),
+    ("23.98", 24000),
+    ("29.97", 30000),
+    ("47.952", 48000),
+    ("59.94", 60000),
+    ("119.88", 120000),
+];
+
+/// Accepts fractions (`24000/1001`), integers (`24`) and decimals (`25.5`).
+/// The usual decimals of ntsc rates (`23.976`, `23.98`, `29.97`, `47.952`,
+/// `59.94` and `119.88`) mean the exact ntsc rate, all other decimals are
+/// taken exactly.
+impl FromStr for FrameRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid frame rate {}, expected something like 24000/1001", s);
+        if let Some((numerator, denominator)) = s.split_once('/') {
+            return FrameRate::new(
+                numerator.trim().parse().map_err(|_| invalid())?,
+                denominator.trim().parse().map_err(|_| invalid())?,
+            );
+        }
+
+        if let Some((_, numerator)) = NTSC_RATES.iter().find(|(ntsc, _)| *ntsc == s.trim()) {
+            return FrameRate::new(*numerator, 1001);
+        }
+
+        let (integer, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+        if integer.is_empty() && fraction.is_empty() || fraction.len() > 6 {
+            return Err(invalid());
+        }
+        let denominator = 10u32.pow(fraction.len() as u32);
+        let integer: u32 =
+            if integer.is_empty() { 0 } else { integer.parse().map_err(|_| invalid())? };
+        let fraction: u32 =
+            if fraction.is_empty() { 0 } else { fraction.parse().map_err(|_| invalid())? };
+        let numerator = integer
+            .checked_mul(denominator)
+            .and_then(|n| n.checked_add(fraction))
+            .ok_or_else(invalid)?;
+        FrameRate::new(numerator, denominator)
+    }
+}
+
+impl Display for FrameRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
     }
 }
 
@@ -26,13 +450,20 @@ pub struct Raw {
     pub width: u64,
     pub height: u64,
     pub bit_depth: u64,
+    pub packing: BitPacking,
     pub cfa: CfaDescriptor,
-    pub fps: f64,
+    pub fps: FrameRate,
+}
+
+impl Raw {
+    /// Whether every sample takes exactly one byte, which is what the nodes
+    /// that dont unpack samples themselves (like `Debayer`) expect
+    pub fn is_8bit(&self) -> bool { self.bit_depth == 8 && self.packing != BitPacking::U16Le }
 }
 
 impl FrameInterpretation for Raw {
     fn required_bytes(&self) -> usize {
-        self.width as usize * self.height as usize * self.bit_depth as usize / 8
+        self.packing.required_bytes(self.bit_depth, self.width as usize * self.height as usize)
     }
 }
 
@@ -40,7 +471,7 @@ impl FrameInterpretation for Raw {
 pub struct Rgb {
     pub width: u64,
     pub height: u64,
-    pub fps: f64,
+    pub fps: FrameRate,
 }
 
 impl FrameInterpretation for Rgb {
@@ -51,9 +482,411 @@ impl FrameInterpretation for Rgb {
 pub struct Rgba {
     pub width: u64,
     pub height: u64,
-    pub fps: f64,
+    pub fps: FrameRate,
 }
 
 impl FrameInterpretation for Rgba {
-    fn required_bytes(&self) -> usize { self.width as usize * self.height as usize * 3 }
+    fn required_bytes(&self) -> usize { self.width as usize * self.height as usize * 4 }
+}
+
+/// Rgb with 16 bit little endian words per channel
+#[derive(Clone, Copy)]
+pub struct Rgb16 {
+    pub width: u64,
+    pub height: u64,
+    pub fps: FrameRate,
+}
+
+impl FrameInterpretation for Rgb16 {
+    fn required_bytes(&self) -> usize { self.width as usize * self.height as usize * 3 * 2 }
+}
+
+/// Rgba with 16 bit little endian words per channel
+#[derive(Clone, Copy)]
+pub struct Rgba16 {
+    pub width: u64,
+    pub height: u64,
+    pub fps: FrameRate,
+}
+
+impl FrameInterpretation for Rgba16 {
+    fn required_bytes(&self) -> usize { self.width as usize * self.height as usize * 4 * 2 }
+}
+
+/// Rgb with a little endian half float per channel, where 1.0 is white
+#[derive(Clone, Copy)]
+pub struct RgbF16 {
+    pub width: u64,
+    pub height: u64,
+    pub fps: FrameRate,
+}
+
+impl FrameInterpretation for RgbF16 {
+    fn required_bytes(&self) -> usize { self.width as usize * self.height as usize * 3 * 2 }
+}
+
+/// Rgb with a little endian f32 per channel, where 1.0 is white
+#[derive(Clone, Copy)]
+pub struct RgbF32 {
+    pub width: u64,
+    pub height: u64,
+    pub fps: FrameRate,
+}
+
+impl FrameInterpretation for RgbF32 {
+    fn required_bytes(&self) -> usize { self.width as usize * self.height as usize * 3 * 4 }
+}
+
+/// The sample formats nodes that create rgb frames (like `Debayer`) can output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RgbFormat {
+    Rgb8,
+    Rgb16,
+    RgbF16,
+    RgbF32,
+}
+
+impl RgbFormat {
+    pub fn port_type(self) -> PortType {
+        match self {
+            RgbFormat::Rgb8 => PortType::Rgb,
+            RgbFormat::Rgb16 => PortType::Rgb16,
+            RgbFormat::RgbF16 => PortType::RgbF16,
+            RgbFormat::RgbF32 => PortType::RgbF32,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            RgbFormat::Rgb8 => 3,
+            RgbFormat::Rgb16 | RgbFormat::RgbF16 => 6,
+            RgbFormat::RgbF32 => 12,
+        }
+    }
+
+    /// The value of white
+    pub fn max_value(self) -> f64 {
+        match self {
+            RgbFormat::Rgb8 => 255.0,
+            RgbFormat::Rgb16 => 65535.0,
+            RgbFormat::RgbF16 | RgbFormat::RgbF32 => 1.0,
+        }
+    }
+
+    /// Creates a frame with the interpretation of this format
+    pub fn frame<Storage: Send + Sync + 'static>(
+        self,
+        width: u64,
+        height: u64,
+        fps: FrameRate,
+        storage: Storage,
+        metadata: FrameMetadata,
+    ) -> Payload {
+        match self {
+            RgbFormat::Rgb8 => {
+                Payload::from(Frame { interp: Rgb { width, height, fps }, storage, metadata })
+            }
+            RgbFormat::Rgb16 => {
+                Payload::from(Frame { interp: Rgb16 { width, height, fps }, storage, metadata })
+            }
+            RgbFormat::RgbF16 => {
+                Payload::from(Frame { interp: RgbF16 { width, height, fps }, storage, metadata })
+            }
+            RgbFormat::RgbF32 => {
+                Payload::from(Frame { interp: RgbF32 { width, height, fps }, storage, metadata })
+            }
+        }
+    }
+}
+
+impl FromStr for RgbFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rgb8" => Ok(RgbFormat::Rgb8),
+            "rgb16" => Ok(RgbFormat::Rgb16),
+            "rgbf16" => Ok(RgbFormat::RgbF16),
+            "rgbf32" => Ok(RgbFormat::RgbF32),
+            _ => Err(anyhow!("unknown rgb format {}, expected rgb8, rgb16, rgbf16 or rgbf32", s)),
+        }
+    }
+}
+
+/// How much the chroma planes of a yuv frame are subsampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// half the width and half the height
+    Yuv420,
+    /// half the width
+    Yuv422,
+    /// no subsampling
+    Yuv444,
+}
+
+impl ChromaSubsampling {
+    /// How many pixels horizontally and vertically share one chroma sample
+    pub fn factors(self) -> (u64, u64) {
+        match self {
+            ChromaSubsampling::Yuv420 => (2, 2),
+            ChromaSubsampling::Yuv422 => (2, 1),
+            ChromaSubsampling::Yuv444 => (1, 1),
+        }
+    }
+}
+
+impl FromStr for ChromaSubsampling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "420" => Ok(ChromaSubsampling::Yuv420),
+            "422" => Ok(ChromaSubsampling::Yuv422),
+            "444" => Ok(ChromaSubsampling::Yuv444),
+            _ => Err(anyhow!("unknown chroma subsampling {}, expected 420, 422 or 444", s)),
+        }
+    }
+}
+
+/// The matrix used to get from rgb to yuv
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvMatrix {
+    Bt601,
+    Bt709,
+}
+
+impl YuvMatrix {
+    /// The weights of red and blue in the luma (`Kr` and `Kb`)
+    pub fn coefficients(self) -> (f32, f32) {
+        match self {
+            YuvMatrix::Bt601 => (0.299, 0.114),
+            YuvMatrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+impl FromStr for YuvMatrix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bt601" => Ok(YuvMatrix::Bt601),
+            "bt709" => Ok(YuvMatrix::Bt709),
+            _ => Err(anyhow!("unknown yuv matrix {}, expected bt601 or bt709", s)),
+        }
+    }
+}
+
+/// Planar 8 bit yuv in limited (16-235) range: the full resolution y plane is
+/// followed by the u and the v plane. The chroma planes are rounded up to whole
+/// samples for odd sizes.
+#[derive(Clone, Copy)]
+pub struct Yuv {
+    pub width: u64,
+    pub height: u64,
+    pub subsampling: ChromaSubsampling,
+    pub matrix: YuvMatrix,
+    pub fps: FrameRate,
+}
+
+impl Yuv {
+    /// The width and height of the u and v planes
+    pub fn chroma_size(&self) -> (u64, u64) {
+        let (horizontal, vertical) = self.subsampling.factors();
+        ((self.width + horizontal - 1) / horizontal, (self.height + vertical - 1) / vertical)
+    }
+
+    pub fn luma_bytes(&self) -> usize { self.width as usize * self.height as usize }
+
+    pub fn chroma_bytes(&self) -> usize {
+        let (width, height) = self.chroma_size();
+        width as usize * height as usize
+    }
+}
+
+impl FrameInterpretation for Yuv {
+    fn required_bytes(&self) -> usize { self.luma_bytes() + 2 * self.chroma_bytes() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline_processing::frame::{BitPacking, CfaColor, CfaDescriptor, FrameRate};
+
+    const PACKINGS: [BitPacking; 3] =
+        [BitPacking::MsbFirst, BitPacking::LsbFirst, BitPacking::U16Le];
+
+    fn rate(s: &str) -> FrameRate { s.parse().unwrap() }
+
+    #[test]
+    fn test_parse_frame_rate() {
+        assert_eq!(rate("24"), FrameRate::new(24, 1).unwrap());
+        assert_eq!(rate("48/2"), FrameRate::new(24, 1).unwrap());
+        assert_eq!(rate(" 25.5 "), FrameRate::new(51, 2).unwrap());
+        assert_eq!(rate(".5"), FrameRate::new(1, 2).unwrap());
+        assert_eq!(rate("24000/1001").to_string(), "24000/1001");
+
+        // only the reduced fraction has to fit into the signed fractions of dng and
+        // gstreamer
+        assert_eq!(rate("4000000000/2"), FrameRate::new(2000000000, 1).unwrap());
+        assert!(FrameRate::new(1, i32::MAX as u32 + 1).is_err());
+
+        for s in ["", ".", "abc", "24/0", "0", "1/2/3", "1.1234567", "5000000000.5", "3000000000/1"]
+            .iter()
+        {
+            assert!(s.parse::<FrameRate>().is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_ntsc_frame_rate() {
+        assert_eq!(rate("23.976"), FrameRate::new(24000, 1001).unwrap());
+        assert_eq!(rate("23.98"), FrameRate::new(24000, 1001).unwrap());
+        assert_eq!(rate("29.97"), FrameRate::new(30000, 1001).unwrap());
+        assert_eq!(rate("47.952"), FrameRate::new(48000, 1001).unwrap());
+        assert_eq!(rate("59.94"), FrameRate::new(60000, 1001).unwrap());
+        assert_eq!(rate(" 119.88 "), FrameRate::new(120000, 1001).unwrap());
+        // other decimals are exact, even if they are a rounding of a ntsc rate
+        assert_eq!(rate("59.9"), FrameRate::new(599, 10).unwrap());
+        assert_eq!(rate("23.9"), FrameRate::new(239, 10).unwrap());
+        assert_eq!(rate("29.970"), FrameRate::new(2997, 100).unwrap());
+        assert_eq!(rate("5000000.5"), FrameRate::new(10000001, 2).unwrap());
+    }
+
+    #[test]
+    fn test_nominal_frame_rate() {
+        assert_eq!(rate("24000/1001").nominal(), 24);
+        assert_eq!(rate("25").nominal(), 25);
+        assert_eq!(rate("0.4").nominal(), 1);
+        assert_eq!(FrameRate::new(i32::MAX as u32, 1).unwrap().nominal(), i32::MAX as u32);
+    }
+
+    fn pack(packing: BitPacking, bit_depth: u64, samples: &[u16]) -> Vec<u8> {
+        let mut data = vec![0u8; packing.required_bytes(bit_depth, samples.len())];
+        packing.write_samples(bit_depth, samples.iter().copied(), &mut data);
+        data
+    }
+
+    fn unpack(packing: BitPacking, bit_depth: u64, data: &[u8]) -> Vec<u16> {
+        let mut samples = Vec::new();
+        packing.read_samples(bit_depth, data, |sample| samples.push(sample));
+        samples
+    }
+
+    #[test]
+    fn test_bit_packing_round_trip() {
+        for &packing in PACKINGS.iter() {
+            for &bit_depth in [8, 10, 12, 16].iter() {
+                let max = ((1u32 << bit_depth) - 1) as u16;
+                // odd counts leave the last byte of the tight packings half used
+                for &len in [1, 2, 3, 7, 64].iter() {
+                    let samples = (0..len as u32)
+                        .map(|i| {
+                            if i == len as u32 - 1 {
+                                max
+                            } else {
+                                (i * 40503 % (max as u32 + 1)) as u16
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let data = pack(packing, bit_depth, &samples);
+                    assert_eq!(
+                        data.len(),
+                        (len * packing.bits_per_sample(bit_depth) as usize + 7) / 8,
+                        "{:?} {} bit",
+                        packing,
+                        bit_depth
+                    );
+                    assert_eq!(
+                        unpack(packing, bit_depth, &data),
+                        samples,
+                        "{:?} {} bit, {} samples",
+                        packing,
+                        bit_depth,
+                        len
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bit_packing_layout() {
+        let samples = [0xabc, 0xdef];
+        assert_eq!(pack(BitPacking::MsbFirst, 12, &samples), [0xab, 0xcd, 0xef]);
+        assert_eq!(pack(BitPacking::LsbFirst, 12, &samples), [0xbc, 0xfa, 0xde]);
+        assert_eq!(pack(BitPacking::U16Le, 12, &samples), [0xbc, 0x0a, 0xef, 0x0d]);
+        // the unused low bits of the last byte are zero
+        assert_eq!(pack(BitPacking::MsbFirst, 12, &[0xabc]), [0xab, 0xc0]);
+        assert_eq!(pack(BitPacking::LsbFirst, 10, &[0x3ff]), [0xff, 0x03]);
+    }
+
+    #[test]
+    fn test_parse_bit_packing() {
+        for &packing in PACKINGS.iter() {
+            assert_eq!(packing.to_string().parse::<BitPacking>().unwrap(), packing);
+        }
+        assert!("msb".parse::<BitPacking>().is_err());
+    }
+
+    fn cfa(s: &str) -> CfaDescriptor { s.parse().unwrap() }
+
+    #[test]
+    fn test_parse_cfa() {
+        assert_eq!(cfa("RGGB"), cfa("RG/GB"));
+        assert_eq!(cfa("rggb"), cfa("RGGB"));
+        assert_eq!(cfa("RGGB"), CfaDescriptor::from_first_red(true, true));
+        assert_eq!(cfa("GRBG"), CfaDescriptor::from_first_red(false, true));
+        assert_eq!(cfa("GBRG"), CfaDescriptor::from_first_red(true, false));
+        assert_eq!(cfa("BGGR"), CfaDescriptor::from_first_red(false, false));
+        assert_eq!(cfa("mono"), CfaDescriptor::Mono);
+        assert_eq!(cfa("MONO"), CfaDescriptor::Mono);
+
+        let quad_bayer = cfa("RRGG/RRGG/GGBB/GGBB");
+        assert_eq!(quad_bayer.size(), (4, 4));
+        assert_eq!(quad_bayer.color_at(1, 1), Some(CfaColor::Red));
+        assert_eq!(quad_bayer.color_at(2, 1), Some(CfaColor::Green));
+        assert_eq!(quad_bayer.color_at(3, 3), Some(CfaColor::Blue));
+        // the pattern repeats over the whole frame
+        assert_eq!(quad_bayer.color_at(5, 9), quad_bayer.color_at(1, 1));
+        assert_eq!(cfa("mono").color_at(3, 4), None);
+
+        // "RéG" has 4 bytes, but only 3 characters
+        for s in
+            ["", "RGGX", "RG/G", "RGB/GB", "RRRRRRRRR", "R/R/R/R/R/R/R/R/R", "RéG", "RGéB"].iter()
+        {
+            assert!(s.parse::<CfaDescriptor>().is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_display_cfa() {
+        for s in ["RGGB", "BGGR", "RRGG/RRGG/GGBB/GGBB", "RGB/GBR/BRG", "G", "mono"].iter() {
+            assert_eq!(cfa(s).to_string(), *s);
+        }
+        assert_eq!(cfa("GR/BG").to_string(), "GRBG");
+    }
+
+    #[test]
+    fn test_shift_cfa() {
+        assert_eq!(cfa("RGGB").shifted(1, 0), cfa("GRBG"));
+        assert_eq!(cfa("RGGB").shifted(0, 1), cfa("GBRG"));
+        assert_eq!(cfa("RGGB").shifted(1, 1), cfa("BGGR"));
+        assert_eq!(cfa("RGGB").shifted(2, 4), cfa("RGGB"));
+        assert_eq!(
+            cfa("RRGG/RRGG/GGBB/GGBB").shifted(1, 2),
+            cfa("GGBB/GGBB/RRGG/RRGG").shifted(1, 0)
+        );
+        assert_eq!(cfa("RRGG/RRGG/GGBB/GGBB").shifted(2, 2), cfa("BBGG/BBGG/GGRR/GGRR"));
+        assert_eq!(cfa("mono").shifted(1, 1), CfaDescriptor::Mono);
+    }
+
+    #[test]
+    fn test_is_bayer() {
+        for s in ["RGGB", "GRBG", "GBRG", "BGGR"].iter() {
+            assert!(cfa(s).is_bayer(), "{}", s);
+        }
+        for s in ["RGBG", "GGRB", "RRGB", "RBBR", "RRGG/RRGG/GGBB/GGBB", "RG", "mono"].iter() {
+            assert!(!cfa(s).is_bayer(), "{}", s);
+        }
+    }
 }
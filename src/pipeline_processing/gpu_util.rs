@@ -8,7 +8,7 @@ use crate::pipeline_processing::{
 };
 use std::sync::Arc;
 use vulkano::{
-    buffer::{BufferUsage, ImmutableBuffer},
+    buffer::{BufferUsage, CpuAccessibleBuffer, ImmutableBuffer},
     command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBuffer},
     device::Queue,
     sync::GpuFuture,
@@ -27,6 +27,7 @@ pub fn to_immutable_buffer<Interpretation: Clone + Send + Sync + 'static>(
             BufferUsage {
                 storage_buffer: true,
                 storage_texel_buffer: true,
+                uniform_texel_buffer: true,
                 transfer_destination: true,
                 ..BufferUsage::none()
             },
@@ -40,7 +41,20 @@ pub fn to_immutable_buffer<Interpretation: Clone + Send + Sync + 'static>(
         )
         .unwrap();
 
-        cbb.copy_buffer(frame.storage.cpu_accessible_buffer(), init).unwrap();
+        let source = match frame.storage.cpu_accessible_buffer() {
+            Some(buffer) => buffer,
+            // frames in normal memory have to be copied into a buffer the gpu can read first
+            None => frame.storage.as_slice(|slice| {
+                CpuAccessibleBuffer::from_iter(
+                    device.clone(),
+                    BufferUsage::transfer_source(),
+                    false,
+                    slice.iter().copied(),
+                )
+                .unwrap()
+            }),
+        };
+        cbb.copy_buffer(source, init).unwrap();
         let cb = cbb.build().unwrap();
         let future = match cb.execute(queue) {
             Ok(f) => f,
@@ -50,7 +64,14 @@ pub fn to_immutable_buffer<Interpretation: Clone + Send + Sync + 'static>(
         (buffer, future)
     };
 
-    (Frame { interp: frame.interp.clone(), storage: buffer.into() }, fut)
+    (
+        Frame {
+            interp: frame.interp.clone(),
+            storage: buffer.into(),
+            metadata: frame.metadata.clone(),
+        },
+        fut,
+    )
 }
 
 pub fn ensure_gpu_buffer<Interpretation: Clone + Send + Sync + 'static>(
@@ -0,0 +1,75 @@
+use crate::pipeline_processing::processing_node::ProcessingNode;
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+
+pub type NodeId = usize;
+
+pub struct GraphNode<N> {
+    pub name: String,
+    pub node: N,
+    pub input: Option<NodeId>,
+}
+
+/// A directed acyclic graph of named processing nodes. The first node is the
+/// source of the pipeline, every other node consumes the output of exactly one
+/// upstream node. The output of a node with several downstream nodes is handed
+/// to all of these branches.
+pub struct ProcessingGraph<N = Arc<dyn ProcessingNode>> {
+    nodes: Vec<GraphNode<N>>,
+}
+
+impl<N> ProcessingGraph<N> {
+    pub fn new(name: &str, source: N) -> Self {
+        Self { nodes: vec![GraphNode { name: name.to_string(), node: source, input: None }] }
+    }
+
+    /// Builds a strictly linear pipeline, where every node consumes the output
+    /// of the node before it.
+    pub fn linear(nodes: impl IntoIterator<Item = (String, N)>) -> Result<Self> {
+        let mut nodes = nodes.into_iter();
+        let (name, source) =
+            nodes.next().ok_or_else(|| anyhow!("a pipeline needs at least one node"))?;
+        let mut graph = Self::new(&name, source);
+        for (name, node) in nodes {
+            graph.add_node(&name, node, graph.len() - 1)?;
+        }
+        Ok(graph)
+    }
+
+    pub fn add_node(&mut self, name: &str, node: N, input: NodeId) -> Result<NodeId> {
+        if input >= self.nodes.len() {
+            return Err(anyhow!("cant connect {} to non existing node {}", name, input));
+        }
+        self.nodes.push(GraphNode { name: name.to_string(), node, input: Some(input) });
+        Ok(self.nodes.len() - 1)
+    }
+
+    pub fn source(&self) -> NodeId { 0 }
+    pub fn len(&self) -> usize { self.nodes.len() }
+    pub fn is_empty(&self) -> bool { self.nodes.is_empty() }
+    pub fn get(&self, id: NodeId) -> &GraphNode<N> { &self.nodes[id] }
+
+    /// Iterates over all nodes in topological order (every node comes after its
+    /// input).
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &GraphNode<N>)> {
+        self.nodes.iter().enumerate()
+    }
+
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        self.iter().filter(|(_, node)| node.input == Some(id)).map(|(id, _)| id).collect()
+    }
+
+    pub fn try_map<M, F: FnMut(&str, N) -> Result<M>>(
+        self,
+        mut func: F,
+    ) -> Result<ProcessingGraph<M>> {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .map(|GraphNode { name, node, input }| {
+                Ok(GraphNode { node: func(&name, node)?, name, input })
+            })
+            .collect::<Result<_>>()?;
+        Ok(ProcessingGraph { nodes })
+    }
+}
@@ -0,0 +1,75 @@
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Information about a frame that is not part of its pixel data. Nodes pass it
+/// on unchanged to the frames they create from their input, so it reaches the
+/// writers at the end of a pipeline.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameMetadata {
+    /// When the frame was captured. Sources that dont know this leave it empty.
+    pub timestamp: Option<SystemTime>,
+    /// The number of the frame as counted by its source (which can differ from
+    /// the frame number of the pipeline, e.g. when looping or after drops)
+    pub sequence_number: Option<u64>,
+    /// How many frames the source lost right before this one, as far as it can
+    /// tell (e.g. from missing numbers in the file names of `RawDirectoryReader`)
+    pub dropped_before: u64,
+    /// The black and white level of the samples, if a node like
+    /// `BlackWhiteLevel` knows them
+    pub levels: Option<Levels>,
+    /// Further source specific values like camera register contents
+    pub tags: BTreeMap<String, String>,
+}
+
+/// The sample value of black for every color of the color filter array
+/// (indexed by `CfaColor`, all the same for mono sensors) and the sample value
+/// at which the sensor clips
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub black: [f64; 3],
+    pub white: f64,
+}
+
+impl FrameMetadata {
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.tags.insert(key.into(), value.to_string());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool { self == &Self::default() }
+
+    /// The timestamp as seconds since the unix epoch
+    pub fn unix_timestamp(&self) -> Option<f64> {
+        self.timestamp
+            .and_then(|timestamp| timestamp.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs_f64())
+    }
+
+    /// The representation used by the writers that persist metadata in side
+    /// car files. Only the fields that are set are included.
+    pub fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        if let Some(timestamp) = self.unix_timestamp() {
+            map.insert("timestamp".to_string(), json!(timestamp));
+        }
+        if let Some(sequence_number) = self.sequence_number {
+            map.insert("sequence-number".to_string(), json!(sequence_number));
+        }
+        if self.dropped_before != 0 {
+            map.insert("dropped-before".to_string(), json!(self.dropped_before));
+        }
+        if let Some(levels) = self.levels {
+            map.insert(
+                "levels".to_string(),
+                json!({ "black": levels.black, "white": levels.white }),
+            );
+        }
+        if !self.tags.is_empty() {
+            map.insert("tags".to_string(), json!(self.tags));
+        }
+        Value::Object(map)
+    }
+}
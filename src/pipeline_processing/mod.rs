@@ -1,88 +1,94 @@
-use crate::{
-    nodes_cpu::bitdepth_convert::BitDepthConverter,
-    nodes_gpu::{bitdepth_convert::GpuBitDepthConverter, debayer::Debayer, display::Display},
-    nodes_io::{
-        reader_raw::{RawBlobReader, RawDirectoryReader},
-        reader_tcp::TcpReader,
-        writer_cinema_dng::CinemaDngWriter,
-        writer_ffmpeg::FfmpegWriter,
-        writer_raw::{RawBlobWriter, RawDirectoryWriter},
-    },
-    pipeline_processing::{
-        parametrizable::{Parameterizable, ParameterizableDescriptor, Parameters},
-        processing_context::ProcessingContext,
-    },
+use crate::pipeline_processing::{
+    parametrizable::{Parameterizable, ParameterizableDescriptor, Parameters},
+    port_type::PortType,
+    processing_context::ProcessingContext,
 };
 use anyhow::{anyhow, Result};
 use processing_node::ProcessingNode;
 use std::{collections::HashMap, sync::Arc};
 
-#[cfg(feature = "gst")]
-use crate::nodes_io::writer_gstreamer::GstWriter;
+#[doc(hidden)]
+pub use inventory;
 
 pub mod buffers;
+pub mod error_policy;
 pub mod execute;
 pub mod frame;
+#[cfg(feature = "gpu")]
 pub mod gpu_util;
+pub mod graph;
+pub mod metadata;
 pub mod parametrizable;
 pub mod payload;
+pub mod pipeline_description;
+pub mod port_type;
 pub mod processing_context;
 pub mod processing_node;
+pub mod progress;
+pub mod unpack;
 
-macro_rules! generate_dynamic_node_creation_functions {
-    ($($x:ty),+ $(,)?) => {
-        pub fn list_available_nodes() -> HashMap<String, ParameterizableDescriptor> {
-            let mut to_return = HashMap::new();
-            $(
-                to_return.insert(<$x>::get_name(), <$x>::describe());
-            )+
-            to_return
+/// The entry of a node type in the registry of available nodes. Nodes are
+/// registered with the `register_node!` macro, which works from any crate that
+/// is linked into the final binary.
+pub struct NodeRegistration {
+    name: fn() -> String,
+    describe: fn() -> ParameterizableDescriptor,
+    create: fn(&Parameters, ProcessingContext) -> Result<Arc<dyn ProcessingNode>>,
+    output_type: fn(&Parameters, PortType) -> Result<PortType>,
+}
+impl NodeRegistration {
+    pub const fn new<T: Parameterizable + ProcessingNode + Send + Sync + 'static>() -> Self {
+        NodeRegistration {
+            name: T::get_name,
+            describe: T::describe,
+            create: |parameters, context| Ok(Arc::new(T::from_parameters(parameters, context)?)),
+            output_type: T::output_type_for,
         }
+    }
+}
+inventory::collect!(NodeRegistration);
 
-        pub fn create_node_from_name(name: &str, parameters: &Parameters, context: ProcessingContext) -> Result<Arc<dyn ProcessingNode>> {
-            $(
-                if name == <$x>::get_name() {
-                    return Ok(Arc::new(<$x>::from_parameters(parameters, context)?))
-                };
-            )+
-
-            Err(anyhow!("no node named {} found", name))
+/// Makes a node available to `list_available_nodes` and `create_node_from_name`
+/// (and therefore to the converter). The node name has to be unique.
+///
+/// ```ignore
+/// register_node!(MyNode);
+/// ```
+#[macro_export]
+macro_rules! register_node {
+    ($node:ty) => {
+        $crate::pipeline_processing::inventory::submit! {
+            $crate::pipeline_processing::NodeRegistration::new::<$node>()
         }
     };
 }
 
+fn find_node(name: &str) -> Result<&'static NodeRegistration> {
+    inventory::iter::<NodeRegistration>
+        .into_iter()
+        .find(|registration| (registration.name)() == name)
+        .ok_or_else(|| anyhow!("no node named {} found", name))
+}
 
-// TODO(robin): this is stupid
-#[cfg(feature = "gst")]
-generate_dynamic_node_creation_functions![
-    //Usb3Reader,
-    RawBlobReader,
-    RawDirectoryReader,
-    BitDepthConverter,
-    Debayer,
-    RawBlobWriter,
-    RawDirectoryWriter,
-    CinemaDngWriter,
-    FfmpegWriter,
-    GstWriter,
-    Display,
-    TcpReader,
-    GpuBitDepthConverter,
-];
+pub fn list_available_nodes() -> HashMap<String, ParameterizableDescriptor> {
+    inventory::iter::<NodeRegistration>
+        .into_iter()
+        .map(|registration| ((registration.name)(), (registration.describe)()))
+        .collect()
+}
 
+pub fn create_node_from_name(
+    name: &str,
+    parameters: &Parameters,
+    context: ProcessingContext,
+) -> Result<Arc<dyn ProcessingNode>> {
+    (find_node(name)?.create)(parameters, context)
+}
 
-#[cfg(not(feature = "gst"))]
-generate_dynamic_node_creation_functions![
-    //Usb3Reader,
-    RawBlobReader,
-    RawDirectoryReader,
-    BitDepthConverter,
-    Debayer,
-    RawBlobWriter,
-    RawDirectoryWriter,
-    CinemaDngWriter,
-    FfmpegWriter,
-    Display,
-    TcpReader,
-    GpuBitDepthConverter,
-];
+pub fn output_type_of_node(
+    name: &str,
+    parameters: &Parameters,
+    input: PortType,
+) -> Result<PortType> {
+    (find_node(name)?.output_type)(parameters, input)
+}
@@ -4,6 +4,7 @@ use std::{any::type_name, convert::TryInto};
 
 use crate::pipeline_processing::{
     frame::{CfaDescriptor, Raw},
+    port_type::PortType,
     processing_context::ProcessingContext,
 };
 use std::collections::HashMap;
@@ -95,9 +96,15 @@ impl Parameters {
         let width = self.get("width")?;
         let height = self.get("height")?;
         let bit_depth = self.get("bit-depth")?;
-        let cfa = CfaDescriptor::from_first_red(self.get("first-red-x")?, self.get("first-red-y")?);
-        let fps = self.get("fps")?;
-        Ok(Raw { bit_depth, width, height, cfa, fps })
+        // first-red-x / first-red-y are still around to shift the pattern by one pixel,
+        // so old pipelines keep working
+        let cfa = self.get::<String>("cfa")?.parse::<CfaDescriptor>()?.shifted(
+            !self.get::<bool>("first-red-x")? as u64,
+            !self.get::<bool>("first-red-y")? as u64,
+        );
+        let packing = self.get::<String>("packing")?.parse()?;
+        let fps = self.get::<String>("fps")?.parse()?;
+        Ok(Raw { bit_depth, width, height, packing, cfa, fps })
     }
 }
 
@@ -176,8 +183,22 @@ impl ParametersDescriptor {
     }
     pub fn with_raw_interpretation(self) -> ParametersDescriptor {
         self.with("bit-depth", Mandatory(ParameterType::IntRange(8, 16)))
+            .with(
+                "packing",
+                Optional(
+                    ParameterType::StringParameter,
+                    ParameterValue::StringParameter("msb-first".to_string()),
+                ),
+            )
             .with("width", Mandatory(ParameterType::IntRange(0, i64::max_value())))
             .with("height", Mandatory(ParameterType::IntRange(0, i64::max_value())))
+            .with(
+                "cfa",
+                Optional(
+                    ParameterType::StringParameter,
+                    ParameterValue::StringParameter("RGGB".to_string()),
+                ),
+            )
             .with(
                 "first-red-x",
                 Optional(ParameterType::BoolParameter, ParameterValue::BoolParameter(true)),
@@ -189,8 +210,8 @@ impl ParametersDescriptor {
             .with(
                 "fps",
                 Optional(
-                    ParameterType::FloatRange(0.0, f64::MAX),
-                    ParameterValue::FloatRange(24.0),
+                    ParameterType::StringParameter,
+                    ParameterValue::StringParameter("24".to_string()),
                 ),
             )
     }
@@ -212,6 +233,27 @@ pub trait Parameterizable {
     where
         Self: Sized;
 
+    /// The payload types this node accepts as input
+    fn input_types(parameters: &Parameters) -> Result<Vec<PortType>>;
+    /// The payload type this node produces for one of its accepted input types
+    fn output_type(parameters: &Parameters, input: PortType) -> Result<PortType>;
+
+    /// Checks that the node accepts `input` and returns the type of its output.
+    /// This allows type checking a whole pipeline before any node is created.
+    fn output_type_for(parameters: &Parameters, input: PortType) -> Result<PortType> {
+        let input_types = Self::input_types(parameters)?;
+        if input_types.iter().any(|accepted| accepted.accepts(&input)) {
+            Self::output_type(parameters, input)
+        } else {
+            Err(anyhow!(
+                "{} accepts {}, but got {}",
+                Self::get_name(),
+                input_types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" or "),
+                input
+            ))
+        }
+    }
+
     fn get_name() -> String {
         Self::NAME
             .map(|v| v.to_string())
@@ -0,0 +1,255 @@
+use crate::pipeline_processing::{
+    graph::{NodeId, ProcessingGraph},
+    list_available_nodes,
+    parametrizable::{ParameterValue, Parameters},
+};
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Map, Value};
+use std::{collections::HashSet, path::Path, str::FromStr};
+
+/// The file formats a pipeline can be described in. Both describe the same
+/// structure: a list of nodes, each with a `type`, the parameters of the node
+/// and optionally an `id` and an `input`, which names the `id` of the node it
+/// consumes. Without an `input` a node consumes the node before it.
+///
+/// ```toml
+/// [[nodes]]
+/// type = "RawDirectoryReader"
+/// file-pattern = "frames/*.raw12"
+/// width = 4096
+/// height = 3072
+/// bit-depth = 12
+///
+/// [[nodes]]
+/// type = "CinemaDngWriter"
+/// path = "dng"
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptionFormat {
+    Toml,
+    Json,
+}
+impl DescriptionFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| {
+                anyhow!("cant guess the format of {} without extension", path.display())
+            })?
+            .parse()
+    }
+}
+impl FromStr for DescriptionFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "toml" => Ok(DescriptionFormat::Toml),
+            "json" => Ok(DescriptionFormat::Json),
+            _ => Err(anyhow!("unknown pipeline format {}, expected toml or json", s)),
+        }
+    }
+}
+
+/// Parses a pipeline description into a graph of node parameters. The graph
+/// nodes are named after the node types.
+pub fn graph_from_description(
+    description: &str,
+    format: DescriptionFormat,
+) -> Result<ProcessingGraph<Parameters>> {
+    let description: Value = match format {
+        DescriptionFormat::Toml => toml::from_str(description)?,
+        DescriptionFormat::Json => serde_json::from_str(description)?,
+    };
+    let entries = description
+        .get("nodes")
+        .and_then(|nodes| nodes.as_array())
+        .ok_or_else(|| anyhow!("a pipeline description needs a list of nodes"))?;
+
+    let mut graph: Option<ProcessingGraph<Parameters>> = None;
+    let mut ids: Vec<(String, NodeId)> = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let mut entry = entry
+            .as_object()
+            .ok_or_else(|| anyhow!("node {} of the pipeline is not a table", i + 1))?
+            .clone();
+        let node_type = take_string(&mut entry, "type")?
+            .ok_or_else(|| anyhow!("node {} of the pipeline has no type", i + 1))?;
+        let id = take_string(&mut entry, "id")?;
+        let input = take_string(&mut entry, "input")?;
+        let parameters = parameters_from_description(&node_type, entry)
+            .with_context(|| format!("invalid node {} ({})", i + 1, node_type))?;
+
+        let node_id = match &mut graph {
+            None if input.is_some() => {
+                return Err(anyhow!("the source node {} cant have an input", node_type))
+            }
+            None => {
+                let new_graph = ProcessingGraph::new(&node_type, parameters);
+                let source = new_graph.source();
+                graph = Some(new_graph);
+                source
+            }
+            Some(graph) => {
+                let input = match input {
+                    None => graph.len() - 1,
+                    Some(input) => ids
+                        .iter()
+                        .find(|(id, _)| *id == input)
+                        .map(|(_, node_id)| *node_id)
+                        .ok_or_else(|| {
+                            anyhow!("the input {} of {} is not defined before it", input, node_type)
+                        })?,
+                };
+                graph.add_node(&node_type, parameters, input)?
+            }
+        };
+        if let Some(id) = id {
+            ids.push((id, node_id));
+        }
+    }
+    graph.ok_or_else(|| anyhow!("the pipeline has to contain at least a source node"))
+}
+
+/// Writes a graph of node parameters (as created by `graph_from_description`)
+/// in the given format
+pub fn graph_to_description(
+    graph: &ProcessingGraph<Parameters>,
+    format: DescriptionFormat,
+) -> Result<String> {
+    // only nodes that are not consumed by the node right after them need an id
+    let referenced: HashSet<NodeId> =
+        graph.iter().filter_map(|(id, node)| node.input.filter(|&input| input + 1 != id)).collect();
+
+    let nodes = graph
+        .iter()
+        .map(|(id, node)| {
+            let mut entry = Map::new();
+            entry.insert("type".to_string(), json!(node.name));
+            if referenced.contains(&id) {
+                entry.insert("id".to_string(), json!(format!("node{}", id)));
+            }
+            if let Some(input) = node.input.filter(|&input| input + 1 != id) {
+                entry.insert("input".to_string(), json!(format!("node{}", input)));
+            }
+            for (key, value) in &node.node.0 {
+                entry.insert(key.clone(), parameter_value_to_json(value));
+            }
+            Value::Object(entry)
+        })
+        .collect::<Vec<_>>();
+    let description = json!({ "nodes": nodes });
+
+    Ok(match format {
+        DescriptionFormat::Toml => toml::to_string(&description)?,
+        DescriptionFormat::Json => serde_json::to_string_pretty(&description)? + "\n",
+    })
+}
+
+fn take_string(entry: &mut Map<String, Value>, key: &str) -> Result<Option<String>> {
+    match entry.remove(key) {
+        None => Ok(None),
+        Some(Value::String(string)) => Ok(Some(string)),
+        Some(value) => Err(anyhow!("{} has to be a string but is {}", key, value)),
+    }
+}
+
+// the values are validated by the same code that parses the command line, so
+// the file and the command line accept exactly the same things
+fn parameters_from_description(
+    node_type: &str,
+    mut entry: Map<String, Value>,
+) -> Result<Parameters> {
+    let available_nodes = list_available_nodes();
+    let node_descriptor = available_nodes
+        .get(node_type)
+        .ok_or_else(|| anyhow!("no node named {} found", node_type))?;
+
+    let parameters = node_descriptor
+        .parameters_descriptor
+        .0
+        .iter()
+        .map(|(key, parameter_type)| {
+            let value = match entry.remove(key) {
+                None => None,
+                Some(Value::String(string)) => Some(string),
+                Some(value @ (Value::Number(_) | Value::Bool(_))) => Some(value.to_string()),
+                Some(value) => return Err(anyhow!("parameter {} cant be {}", key, value)),
+            };
+            let value = parameter_type
+                .parse(value.as_deref())
+                .with_context(|| format!("invalid parameter {}", key))?;
+            Ok((key.to_string(), value))
+        })
+        .collect::<Result<_>>()?;
+
+    match entry.keys().next() {
+        None => Ok(Parameters(parameters)),
+        Some(key) => Err(anyhow!("{} has no parameter {}", node_type, key)),
+    }
+}
+
+fn parameter_value_to_json(value: &ParameterValue) -> Value {
+    match value {
+        ParameterValue::FloatRange(v) => json!(v),
+        ParameterValue::IntRange(v) => json!(v),
+        ParameterValue::StringParameter(v) => json!(v),
+        ParameterValue::BoolParameter(v) => json!(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline_processing::{
+        graph::ProcessingGraph,
+        parametrizable::Parameters,
+        pipeline_description::{graph_from_description, graph_to_description, DescriptionFormat},
+    };
+    use itertools::Itertools;
+
+    // the nodes with their inputs and parameters in a comparable form
+    fn nodes(graph: &ProcessingGraph<Parameters>) -> Vec<(String, Option<usize>, Vec<String>)> {
+        graph
+            .iter()
+            .map(|(_, node)| {
+                let parameters = node.node.0.iter().map(|(k, v)| format!("{}={:?}", k, v)).sorted();
+                (node.name.clone(), node.input, parameters.collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_description_round_trip() {
+        let description = r#"
+            [[nodes]]
+            type = "RawBlobReader"
+            id = "reader"
+            file = "frames.raw12"
+            width = 4096
+            height = 3072
+            bit-depth = 12
+            fps = "24000/1001"
+
+            [[nodes]]
+            type = "BitDepthConverter"
+            output = "16bit"
+
+            [[nodes]]
+            type = "RawBlobWriter"
+            path = "frames.raw16"
+
+            [[nodes]]
+            type = "RawBlobWriter"
+            input = "reader"
+            path = "copy.raw12"
+        "#;
+        let graph = graph_from_description(description, DescriptionFormat::Toml).unwrap();
+        assert_eq!(graph.get(3).input, Some(0));
+
+        for &format in [DescriptionFormat::Toml, DescriptionFormat::Json].iter() {
+            let dumped = graph_to_description(&graph, format).unwrap();
+            let parsed = graph_from_description(&dumped, format).unwrap();
+            assert_eq!(nodes(&parsed), nodes(&graph), "{}", dumped);
+        }
+    }
+}
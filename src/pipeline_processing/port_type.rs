@@ -0,0 +1,56 @@
+use std::fmt::{self, Display, Formatter};
+
+/// The kind of payload a node consumes or produces. Whether a frame lives in a
+/// cpu or gpu buffer is not part of the type, as nodes convert between these
+/// on demand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortType {
+    /// No frame at all. This is the input of sources and the output of sinks.
+    Empty,
+    /// Raw sensor data. `None` stands for any bit depth.
+    Raw {
+        bit_depth: Option<u64>,
+    },
+    Rgb,
+    Rgb16,
+    Rgba16,
+    RgbF16,
+    RgbF32,
+    /// Planar yuv of any subsampling
+    Yuv,
+}
+
+impl PortType {
+    pub fn raw(bit_depth: u64) -> Self { PortType::Raw { bit_depth: Some(bit_depth) } }
+    pub fn any_raw() -> Self { PortType::Raw { bit_depth: None } }
+
+    /// Whether a node that declares `self` as input can consume payloads of the
+    /// `provided` type. A raw output of unknown bit depth is accepted by every
+    /// raw input, as it can only be checked at runtime.
+    pub fn accepts(&self, provided: &PortType) -> bool {
+        match (self, provided) {
+            (
+                PortType::Raw { bit_depth: Some(accepted) },
+                PortType::Raw { bit_depth: Some(got) },
+            ) => accepted == got,
+            (PortType::Raw { .. }, PortType::Raw { .. }) => true,
+            (accepted, got) => accepted == got,
+        }
+    }
+}
+
+impl Display for PortType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PortType::Empty => write!(f, "nothing"),
+            PortType::Raw { bit_depth: Some(bit_depth) } => write!(f, "Raw ({} bit)", bit_depth),
+            PortType::Raw { bit_depth: None } => write!(f, "Raw"),
+            PortType::Rgb => write!(f, "Rgb"),
+            PortType::Rgb16 => write!(f, "Rgb16"),
+            PortType::Rgba16 => write!(f, "Rgba16"),
+            PortType::RgbF16 => write!(f, "RgbF16"),
+            PortType::RgbF32 => write!(f, "RgbF32"),
+            PortType::Yuv => write!(f, "Yuv"),
+        }
+    }
+}
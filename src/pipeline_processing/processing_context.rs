@@ -1,10 +1,14 @@
+#[cfg(feature = "gpu")]
+use crate::pipeline_processing::buffers::GpuBuffer;
 use crate::pipeline_processing::{
-    buffers::{CpuBuffer, GpuBuffer},
-    frame::Frame,
+    buffers::CpuBuffer,
+    frame::{Frame, FrameInterpretation, Raw, Rgb, Rgb16, RgbF16, RgbF32, Rgba, Rgba16, Yuv},
+    metadata::FrameMetadata,
     payload::Payload,
 };
 use anyhow::{anyhow, Result};
 use std::sync::Arc;
+#[cfg(feature = "gpu")]
 use vulkano::{
     buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer},
     command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBuffer},
@@ -13,6 +17,7 @@ use vulkano::{
     Version,
 };
 
+#[cfg(feature = "gpu")]
 #[derive(Clone)]
 struct VulkanContext {
     device: Arc<Device>,
@@ -21,8 +26,17 @@ struct VulkanContext {
 
 #[derive(Clone)]
 pub struct ProcessingContext {
+    #[cfg(feature = "gpu")]
     vulkan_device: Option<VulkanContext>,
 }
+#[cfg(not(feature = "gpu"))]
+impl Default for ProcessingContext {
+    fn default() -> Self {
+        println!("using cpu only processing (built without gpu support)");
+        Self {}
+    }
+}
+#[cfg(feature = "gpu")]
 impl Default for ProcessingContext {
     fn default() -> Self {
         let vk_device =
@@ -61,8 +75,9 @@ impl Default for ProcessingContext {
 }
 impl ProcessingContext {
     pub unsafe fn get_uninit_cpu_buffer(&self, len: usize) -> CpuBuffer {
+        #[cfg(feature = "gpu")]
         if let Some(vulkan_context) = &self.vulkan_device {
-            CpuAccessibleBuffer::uninitialized_array(
+            return CpuAccessibleBuffer::uninitialized_array(
                 vulkan_context.device.clone(),
                 len as _,
                 BufferUsage {
@@ -75,11 +90,11 @@ impl ProcessingContext {
                 true,
             )
             .unwrap()
-            .into()
-        } else {
-            unimplemented!()
+            .into();
         }
+        CpuBuffer::new_heap(len)
     }
+    #[cfg(feature = "gpu")]
     fn to_cpu_buffer<Interpretation: Clone + Send + Sync + 'static>(
         &self,
         frame: Arc<Frame<Interpretation, GpuBuffer>>,
@@ -94,7 +109,10 @@ impl ProcessingContext {
             queue.family(),
             CommandBufferUsage::MultipleSubmit,
         )?;
-        cbb.copy_buffer(frame.storage.typed(), buffer.cpu_accessible_buffer()).unwrap();
+        let target = buffer
+            .cpu_accessible_buffer()
+            .ok_or_else(|| anyhow!("cant download a frame from the gpu into a heap buffer"))?;
+        cbb.copy_buffer(frame.storage.typed(), target).unwrap();
         let cb = cbb.build().unwrap();
         let future = match cb.execute(queue) {
             Ok(f) => f,
@@ -104,25 +122,71 @@ impl ProcessingContext {
         // dropping this future blocks this thread until the gpu finished the work
         drop(future);
 
-        Ok(Frame { interp: frame.interp.clone(), storage: buffer })
+        Ok(Frame {
+            interp: frame.interp.clone(),
+            storage: buffer,
+            metadata: frame.metadata.clone(),
+        })
     }
     pub fn ensure_cpu_buffer<Interpretation: Clone + Send + Sync + 'static>(
         &self,
         payload: &mut Payload,
     ) -> anyhow::Result<Arc<Frame<Interpretation, CpuBuffer>>> {
         if let Ok(frame) = payload.downcast::<Frame<Interpretation, CpuBuffer>>() {
-            Ok(frame)
-        } else if let Ok(frame) = payload.downcast::<Frame<Interpretation, GpuBuffer>>() {
-            Ok(Arc::new(self.to_cpu_buffer(frame)?))
+            return Ok(frame);
+        }
+        #[cfg(feature = "gpu")]
+        if let Ok(frame) = payload.downcast::<Frame<Interpretation, GpuBuffer>>() {
+            return Ok(Arc::new(self.to_cpu_buffer(frame)?));
+        }
+        Err(anyhow!(
+            "wanted a frame with interpretation {}, but the payload was of type {}",
+            std::any::type_name::<Interpretation>(),
+            payload.type_name
+        ))
+    }
+
+    /// Creates a black frame with the same interpretation and metadata as
+    /// the given frame. The new frame is always stored in a cpu buffer.
+    pub fn black_frame_like(&self, payload: &Payload) -> Result<Payload> {
+        fn black_frame<Interpretation: FrameInterpretation + Send + Sync + 'static>(
+            context: &ProcessingContext,
+            (interp, metadata): (Interpretation, FrameMetadata),
+        ) -> Payload {
+            let mut buffer = unsafe { context.get_uninit_cpu_buffer(interp.required_bytes()) };
+            buffer.as_mut_slice(|slice| slice.fill(0));
+            Payload::from(Frame { interp, storage: buffer, metadata })
+        }
+
+        if let Some(frame) = frame_parts::<Raw>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some(frame) = frame_parts::<Rgb>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some(frame) = frame_parts::<Rgba>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some(frame) = frame_parts::<Rgb16>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some(frame) = frame_parts::<Rgba16>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some(frame) = frame_parts::<RgbF16>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some(frame) = frame_parts::<RgbF32>(payload) {
+            Ok(black_frame(self, frame))
+        } else if let Some((interp, metadata)) = frame_parts::<Yuv>(payload) {
+            // zero chroma would be green, black is at 16 in limited range
+            let mut buffer = unsafe { self.get_uninit_cpu_buffer(interp.required_bytes()) };
+            buffer.as_mut_slice(|slice| {
+                let (luma, chroma) = slice.split_at_mut(interp.luma_bytes());
+                luma.fill(16);
+                chroma.fill(128);
+            });
+            Ok(Payload::from(Frame { interp, storage: buffer, metadata }))
         } else {
-            Err(anyhow!(
-                "wanted a frame with interpretation {}, but the payload was of type {}",
-                std::any::type_name::<Interpretation>(),
-                payload.type_name
-            ))
+            Err(anyhow!("cant create a black frame like a payload of type {}", payload.type_name))
         }
     }
 
+    #[cfg(feature = "gpu")]
     pub fn require_vulkan(&self) -> Result<(Arc<Device>, Vec<Arc<Queue>>)> {
         if let Some(vulkan_context) = &self.vulkan_device {
             Ok((vulkan_context.device.clone(), vulkan_context.queues.clone()))
@@ -131,3 +195,17 @@ impl ProcessingContext {
         }
     }
 }
+
+// the interpretation and metadata of a frame, regardless of where it is stored
+fn frame_parts<Interpretation: Clone + Send + Sync + 'static>(
+    payload: &Payload,
+) -> Option<(Interpretation, FrameMetadata)> {
+    if let Ok(frame) = payload.downcast::<Frame<Interpretation, CpuBuffer>>() {
+        return Some((frame.interp.clone(), frame.metadata.clone()));
+    }
+    #[cfg(feature = "gpu")]
+    if let Ok(frame) = payload.downcast::<Frame<Interpretation, GpuBuffer>>() {
+        return Some((frame.interp.clone(), frame.metadata.clone()));
+    }
+    None
+}
@@ -2,6 +2,9 @@ use crate::pipeline_processing::payload::Payload;
 
 use super::execute::ProcessingStageLockWaiter;
 
+/// A node of a processing pipeline. The executor calls `start` on every node
+/// before the first frame, then `process` for every frame and finally `flush`
+/// and `finish` on every node after the last frame.
 pub trait ProcessingNode: Send + Sync {
     fn process(
         &self,
@@ -9,4 +12,13 @@ pub trait ProcessingNode: Send + Sync {
         frame_lock: ProcessingStageLockWaiter,
     ) -> anyhow::Result<Option<Payload>>;
     fn size_hint(&self) -> Option<u64> { None }
+
+    /// Called once before the first frame is processed.
+    fn start(&self) -> anyhow::Result<()> { Ok(()) }
+    /// Called once after the last frame went through the whole pipeline. Data
+    /// the node buffered internally should be written out here.
+    fn flush(&self) -> anyhow::Result<()> { Ok(()) }
+    /// Called once after all nodes were flushed. This is the place to close
+    /// files, wait for child processes and join threads.
+    fn finish(&self) -> anyhow::Result<()> { Ok(()) }
 }
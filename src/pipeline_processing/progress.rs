@@ -0,0 +1,263 @@
+use crate::pipeline_processing::{
+    error_policy::{FrameFailure, FrameRecovery},
+    execute::ProcessingStageLock,
+    graph::{NodeId, ProcessingGraph},
+};
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    /// `start` was not called yet
+    Pending,
+    /// The node was started and processes frames
+    Running,
+    /// `flush` or `finish` are being called
+    Finishing,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeProgress {
+    pub name: String,
+    pub state: NodeState,
+    pub frames_processed: u64,
+    /// The number of frames currently inside `process` of this node
+    pub frames_in_process: u64,
+    /// The total time spent in `process` of this node, including the time
+    /// waiting for previous frames
+    pub process_time: Duration,
+    /// The total time `process` was blocked in
+    /// `ProcessingStageLockWaiter::wait`
+    pub wait_time: Duration,
+    /// The total time between the input of a frame being ready and `process`
+    /// being called. For the source this includes waiting for a free slot for
+    /// a new frame.
+    pub queue_time: Duration,
+}
+
+/// A snapshot of the progress of a running pipeline
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// The number of frames that went through the whole pipeline
+    pub frames_done: u64,
+    /// The number of frames the source will produce, if it knows that
+    pub total_frames: Option<u64>,
+    /// The rate of finished frames over the last second
+    pub fps: f64,
+    pub elapsed: Duration,
+    /// The number of frames that failed in some node and were skipped or
+    /// substituted
+    pub frames_failed: u64,
+    pub nodes: Vec<NodeProgress>,
+}
+
+struct NodeStatus {
+    name: String,
+    state: Mutex<NodeState>,
+    frames_processed: AtomicU64,
+    frames_in_process: AtomicU64,
+    process_nanos: AtomicU64,
+    queue_nanos: AtomicU64,
+    stage_lock: ProcessingStageLock,
+}
+
+/// The state of a pipeline execution that is shared between the executor and
+/// the handles of the pipeline
+pub(crate) struct ExecutionState {
+    cancelled: AtomicBool,
+    start_time: Instant,
+    total_frames: Option<u64>,
+    frames_done: AtomicU64,
+    recent_frames: Mutex<VecDeque<Instant>>,
+    failures: Mutex<Vec<FrameFailure>>,
+    nodes: Vec<NodeStatus>,
+}
+
+impl ExecutionState {
+    pub(crate) fn new(graph: &ProcessingGraph) -> Self {
+        ExecutionState {
+            cancelled: AtomicBool::new(false),
+            start_time: Instant::now(),
+            total_frames: graph.get(graph.source()).node.size_hint(),
+            frames_done: AtomicU64::new(0),
+            recent_frames: Mutex::new(VecDeque::new()),
+            failures: Mutex::new(Vec::new()),
+            nodes: graph
+                .iter()
+                .map(|(_, node)| NodeStatus {
+                    name: node.name.clone(),
+                    state: Mutex::new(NodeState::Pending),
+                    frames_processed: AtomicU64::new(0),
+                    frames_in_process: AtomicU64::new(0),
+                    process_nanos: AtomicU64::new(0),
+                    queue_nanos: AtomicU64::new(0),
+                    stage_lock: ProcessingStageLock::new(),
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn cancel(&self) { self.cancelled.store(true, Ordering::SeqCst) }
+    pub(crate) fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::SeqCst) }
+    pub(crate) fn frames_done(&self) -> u64 { self.frames_done.load(Ordering::SeqCst) }
+    pub(crate) fn elapsed(&self) -> Duration { self.start_time.elapsed() }
+
+    pub(crate) fn set_node_state(&self, node: NodeId, state: NodeState) {
+        *self.nodes[node].state.lock().unwrap() = state;
+    }
+    // every node has its own lock, so the ordering is kept per branch
+    pub(crate) fn stage_lock(&self, node: NodeId) -> &ProcessingStageLock {
+        &self.nodes[node].stage_lock
+    }
+
+    pub(crate) fn node_queued(&self, node: NodeId, duration: Duration) {
+        self.nodes[node].queue_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+    pub(crate) fn node_process_started(&self, node: NodeId) {
+        self.nodes[node].frames_in_process.fetch_add(1, Ordering::SeqCst);
+    }
+    pub(crate) fn node_process_ended(&self, node: NodeId, success: bool, duration: Duration) {
+        self.nodes[node].frames_in_process.fetch_sub(1, Ordering::SeqCst);
+        self.nodes[node].process_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+        if success {
+            self.nodes[node].frames_processed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn frame_done(&self) {
+        self.frames_done.fetch_add(1, Ordering::SeqCst);
+        let mut recent_frames = self.recent_frames.lock().unwrap();
+        recent_frames.push_back(Instant::now());
+        drop_old_frames(&mut recent_frames);
+    }
+
+    pub(crate) fn record_failure(&self, failure: FrameFailure) {
+        self.failures.lock().unwrap().push(failure);
+    }
+    pub(crate) fn failures(&self) -> Vec<FrameFailure> {
+        let mut failures = self.failures.lock().unwrap().clone();
+        failures.sort_by_key(|failure| failure.frame);
+        failures
+    }
+
+    pub(crate) fn progress(&self) -> Progress {
+        let elapsed = self.elapsed();
+        let fps = {
+            let mut recent_frames = self.recent_frames.lock().unwrap();
+            drop_old_frames(&mut recent_frames);
+            recent_frames.len() as f64 / elapsed.min(FPS_WINDOW).as_secs_f64().max(f64::EPSILON)
+        };
+
+        Progress {
+            frames_done: self.frames_done(),
+            total_frames: self.total_frames,
+            fps,
+            elapsed,
+            frames_failed: self
+                .failures
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|failure| !matches!(failure.recovery, FrameRecovery::Retried(_)))
+                .count() as u64,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| NodeProgress {
+                    name: node.name.clone(),
+                    state: *node.state.lock().unwrap(),
+                    frames_processed: node.frames_processed.load(Ordering::SeqCst),
+                    frames_in_process: node.frames_in_process.load(Ordering::SeqCst),
+                    process_time: Duration::from_nanos(node.process_nanos.load(Ordering::Relaxed)),
+                    wait_time: node.stage_lock.time_waited(),
+                    queue_time: Duration::from_nanos(node.queue_nanos.load(Ordering::Relaxed)),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl NodeProgress {
+    // the time per processed frame in milliseconds
+    fn per_frame_ms(&self, duration: Duration) -> f64 {
+        duration.as_secs_f64() * 1000.0 / self.frames_processed.max(1) as f64
+    }
+}
+
+impl Progress {
+    /// Formats the time every node spent on frames as a table. `threads` is the
+    /// average number of threads that were doing actual work in a node, the
+    /// node with the highest value is likely the bottleneck.
+    pub fn profile_table(&self) -> String {
+        let name_width = self.nodes.iter().map(|node| node.name.len()).max().unwrap_or(0).max(4);
+        let mut table = format!(
+            "{:<name_width$} {:>8} {:>8} {:>12} {:>12} {:>12} {:>8}\n",
+            "node",
+            "frames",
+            "fps",
+            "process ms",
+            "waiting ms",
+            "queued ms",
+            "threads",
+            name_width = name_width
+        );
+        for node in &self.nodes {
+            let busy_time = node.process_time.saturating_sub(node.wait_time);
+            table += &format!(
+                "{:<name_width$} {:>8} {:>8.1} {:>12.2} {:>12.2} {:>12.2} {:>8.2}\n",
+                node.name,
+                node.frames_processed,
+                node.frames_processed as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON),
+                node.per_frame_ms(node.process_time),
+                node.per_frame_ms(node.wait_time),
+                node.per_frame_ms(node.queue_time),
+                busy_time.as_secs_f64() / self.elapsed.as_secs_f64().max(f64::EPSILON),
+                name_width = name_width
+            );
+        }
+        table
+    }
+
+    /// The same information as `profile_table` as a single line of json
+    pub fn profile_json(&self) -> String {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                json!({
+                    "name": node.name,
+                    "frames": node.frames_processed,
+                    "process_ms": node.process_time.as_secs_f64() * 1000.0,
+                    "wait_ms": node.wait_time.as_secs_f64() * 1000.0,
+                    "queue_ms": node.queue_time.as_secs_f64() * 1000.0,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "frames_done": self.frames_done,
+            "frames_failed": self.frames_failed,
+            "elapsed_ms": self.elapsed.as_secs_f64() * 1000.0,
+            "fps": self.fps,
+            "nodes": nodes,
+        })
+        .to_string()
+    }
+}
+
+fn drop_old_frames(recent_frames: &mut VecDeque<Instant>) {
+    let now = Instant::now();
+    while recent_frames.front().is_some_and(|&time| now - time > FPS_WINDOW) {
+        recent_frames.pop_front();
+    }
+}
@@ -0,0 +1,208 @@
+//! Fast unpacking of raw frames into one `u16` per sample.
+//!
+//! `BitPacking::read_samples` handles every packing and bit depth, but walks
+//! the data one byte at a time. The functions here produce the same samples
+//! with specialized code for the common 10, 12 and 14 bit packings (using
+//! SSE4.1 on x86_64 cpus that support it) and split the frame into rows that
+//! are unpacked in parallel.
+
+use crate::pipeline_processing::frame::{BitPacking, FrameInterpretation, Raw};
+use rayon::prelude::*;
+
+/// Unpacks the samples in `input` into `output`, one `u16` per sample, until
+/// either of them runs out. Gives the same samples as
+/// `BitPacking::read_samples`.
+pub fn unpack(packing: BitPacking, bit_depth: u64, input: &[u8], output: &mut [u16]) {
+    match (packing, bit_depth) {
+        (BitPacking::U16Le, _) => {
+            for (sample, word) in output.iter_mut().zip(input.chunks_exact(2)) {
+                *sample = u16::from_le_bytes([word[0], word[1]]);
+            }
+        }
+        (_, 8) => {
+            for (sample, &byte) in output.iter_mut().zip(input) {
+                *sample = byte as u16;
+            }
+        }
+        (_, 10) => unpack_fast::<10>(packing, input, output),
+        (_, 12) => unpack_fast::<12>(packing, input, output),
+        (_, 14) => unpack_fast::<14>(packing, input, output),
+        _ => unpack_scalar(packing, bit_depth, input, output),
+    }
+}
+
+/// The reference implementation, which is just `BitPacking::read_samples`
+pub fn unpack_scalar(packing: BitPacking, bit_depth: u64, input: &[u8], output: &mut [u16]) {
+    let mut output = output.iter_mut();
+    packing.read_samples(bit_depth, input, |sample| {
+        if let Some(output) = output.next() {
+            *output = sample;
+        }
+    });
+}
+
+/// Unpacks a whole frame in parallel. The frame is split into chunks of rows,
+/// which start at a byte boundary in both `input` and `output`. `func` gets
+/// the first row of every chunk, its unpacked samples and the part of
+/// `output` that belongs to these rows, when `output` stores every sample
+/// with `output_bits_per_sample` bits.
+pub fn unpack_rows(
+    interp: &Raw,
+    input: &[u8],
+    output: &mut [u8],
+    output_bits_per_sample: u64,
+    func: impl Fn(usize, &[u16], &mut [u8]) + Sync,
+) {
+    let input_bits_per_sample = interp.packing.bits_per_sample(interp.bit_depth);
+    let width = interp.width as usize;
+    let rows_per_chunk = (1..=8)
+        .find(|rows| {
+            let samples = (rows * width) as u64;
+            (samples * input_bits_per_sample) % 8 == 0
+                && (samples * output_bits_per_sample) % 8 == 0
+        })
+        .unwrap();
+    let samples_per_chunk = rows_per_chunk * width;
+    let input_chunk = samples_per_chunk * input_bits_per_sample as usize / 8;
+    let output_chunk = samples_per_chunk * output_bits_per_sample as usize / 8;
+    let samples = width * interp.height as usize;
+    let input = &input[..interp.required_bytes().min(input.len())];
+
+    output
+        .par_chunks_mut(output_chunk)
+        .zip(input.par_chunks(input_chunk))
+        .enumerate()
+        .for_each_init(Vec::new, |buffer, (i, (output, input))| {
+            buffer.resize(samples_per_chunk.min(samples - i * samples_per_chunk), 0);
+            unpack(interp.packing, interp.bit_depth, input, buffer);
+            func(i * rows_per_chunk, buffer, output);
+        });
+}
+
+// every group of 8 samples starts at a byte boundary and is BITS bytes long
+fn unpack_fast<const BITS: usize>(packing: BitPacking, input: &[u8], output: &mut [u16]) {
+    let groups = (input.len() / BITS).min(output.len() / 8);
+    let done = simd::unpack_groups(packing, BITS, input, &mut output[..groups * 8]);
+
+    let mask = (1u64 << BITS) - 1;
+    let input_groups = input[done * BITS..groups * BITS].chunks_exact(BITS / 2);
+    for (group, output) in input_groups.zip(output[done * 8..].chunks_exact_mut(4)) {
+        let mut bytes = [0u8; 8];
+        let word = match packing {
+            BitPacking::MsbFirst => {
+                bytes[8 - BITS / 2..].copy_from_slice(group);
+                u64::from_be_bytes(bytes)
+            }
+            _ => {
+                bytes[..BITS / 2].copy_from_slice(group);
+                u64::from_le_bytes(bytes)
+            }
+        };
+        for (i, output) in output.iter_mut().enumerate() {
+            let shift = match packing {
+                BitPacking::MsbFirst => (3 - i) * BITS,
+                _ => i * BITS,
+            };
+            *output = ((word >> shift) & mask) as u16;
+        }
+    }
+
+    unpack_scalar(packing, BITS as u64, &input[groups * BITS..], &mut output[groups * 8..]);
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use crate::pipeline_processing::frame::BitPacking;
+    use std::arch::x86_64::*;
+
+    /// Unpacks as many of the groups of 8 samples as possible and returns
+    /// their number
+    pub fn unpack_groups(
+        packing: BitPacking,
+        bit_depth: usize,
+        input: &[u8],
+        output: &mut [u16],
+    ) -> usize {
+        if is_x86_feature_detected!("sse4.1") {
+            unsafe { unpack_groups_sse41(packing, bit_depth, input, output) }
+        } else {
+            0
+        }
+    }
+
+    // Every sample is assembled from the 3 bytes it touches in a 32 bit lane,
+    // shifted into place with a multiplication (sse has no per lane shifts)
+    // and a shift by the same amount for all lanes, and masked.
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn unpack_groups_sse41(
+        packing: BitPacking,
+        bit_depth: usize,
+        input: &[u8],
+        output: &mut [u16],
+    ) -> usize {
+        let mut shuffles = [[0x80u8; 16]; 2];
+        let mut factors = [[0u32; 4]; 2];
+        for sample in 0..8 {
+            let (byte, offset) = ((sample * bit_depth) / 8, (sample * bit_depth) % 8);
+            let lane = &mut shuffles[sample / 4][(sample % 4) * 4..][..3];
+            match packing {
+                BitPacking::MsbFirst => {
+                    lane.copy_from_slice(&[byte as u8 + 2, byte as u8 + 1, byte as u8]);
+                    factors[sample / 4][sample % 4] = 1 << offset;
+                }
+                _ => {
+                    lane.copy_from_slice(&[byte as u8, byte as u8 + 1, byte as u8 + 2]);
+                    factors[sample / 4][sample % 4] = 1 << (8 - offset);
+                }
+            }
+        }
+        let shift = match packing {
+            BitPacking::MsbFirst => 24 - bit_depth as i32,
+            _ => 8,
+        };
+
+        let shuffle_low = _mm_loadu_si128(shuffles[0].as_ptr() as *const __m128i);
+        let shuffle_high = _mm_loadu_si128(shuffles[1].as_ptr() as *const __m128i);
+        let factor_low = _mm_loadu_si128(factors[0].as_ptr() as *const __m128i);
+        let factor_high = _mm_loadu_si128(factors[1].as_ptr() as *const __m128i);
+        let shift = _mm_cvtsi32_si128(shift);
+        let mask = _mm_set1_epi32((1 << bit_depth) - 1);
+
+        // every load reads 16 bytes, so the last group(s) are left to the caller
+        let groups = if input.len() < 16 {
+            0
+        } else {
+            ((input.len() - 16) / bit_depth + 1).min(output.len() / 8)
+        };
+        for group in 0..groups {
+            let data = _mm_loadu_si128(input.as_ptr().add(group * bit_depth) as *const __m128i);
+            let low = unpack_lanes(data, shuffle_low, factor_low, shift, mask);
+            let high = unpack_lanes(data, shuffle_high, factor_high, shift, mask);
+            _mm_storeu_si128(
+                output.as_mut_ptr().add(group * 8) as *mut __m128i,
+                _mm_packus_epi32(low, high),
+            );
+        }
+        groups
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn unpack_lanes(
+        data: __m128i,
+        shuffle: __m128i,
+        factor: __m128i,
+        shift: __m128i,
+        mask: __m128i,
+    ) -> __m128i {
+        let lanes = _mm_mullo_epi32(_mm_shuffle_epi8(data, shuffle), factor);
+        _mm_and_si128(_mm_srl_epi32(lanes, shift), mask)
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod simd {
+    use crate::pipeline_processing::frame::BitPacking;
+
+    pub fn unpack_groups(_: BitPacking, _: usize, _: &[u8], _: &mut [u16]) -> usize { 0 }
+}
@@ -0,0 +1,270 @@
+mod common;
+
+use common::{
+    interp,
+    pack,
+    parameters,
+    raw_frame,
+    raw_frame_from_bytes,
+    raw_frame_with_metadata,
+    string,
+};
+use recorder::{
+    nodes_cpu::{
+        black_white_level::BlackWhiteLevel,
+        dark_frame::DarkFrameSubtraction,
+        flat_field::FlatFieldCorrection,
+    },
+    pipeline_processing::{
+        execute::ProcessingStageLock,
+        frame::{BitPacking, Raw},
+        metadata::{FrameMetadata, Levels},
+        parametrizable::{ParameterValue, Parameterizable, Parameters},
+        payload::Payload,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
+};
+use std::path::{Path, PathBuf};
+
+// runs the node and returns the unpacked samples and the metadata of the output
+fn process(node: &dyn ProcessingNode, input: Payload) -> (Vec<u16>, FrameMetadata) {
+    let mut output = common::process(node, &input);
+    let frame = ProcessingContext::default().ensure_cpu_buffer::<Raw>(&mut output).unwrap();
+    let mut samples = vec![];
+    frame.storage.as_slice(|data| {
+        frame
+            .interp
+            .packing
+            .read_samples(frame.interp.bit_depth, data, |sample| samples.push(sample))
+    });
+    samples.truncate((frame.interp.width * frame.interp.height) as usize);
+    (samples, frame.metadata.clone())
+}
+
+fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("recorder-test-{}-{}", std::process::id(), name));
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn black_white_level_scales_every_color() {
+    for &packing in [BitPacking::MsbFirst, BitPacking::LsbFirst, BitPacking::U16Le].iter() {
+        // an odd width, so that rows of packed frames dont start at byte boundaries
+        let interp = interp(5, 4, 12, packing);
+        // RGGB: the black levels are 100 (red), 200 (green) and 300 (blue)
+        let black = |x: usize, y: usize| [[100, 200], [200, 300]][y % 2][x % 2];
+        let input = (0..20).map(|i| black(i % 5, i / 5) + 50 * (i as u16 % 3)).collect::<Vec<_>>();
+
+        let node = BlackWhiteLevel::from_parameters(
+            &parameters::<BlackWhiteLevel>(&[
+                ("black-level", string("100, 200, 300")),
+                ("white-level", string("2101")),
+            ]),
+            ProcessingContext::default(),
+        )
+        .unwrap();
+        let (output, metadata) = process(&node, raw_frame(interp, &input));
+
+        let expected = (0..20)
+            .map(|i| {
+                let black = black(i % 5, i / 5) as f64;
+                ((input[i] as f64 - black) * 4095.0 / (2101.0 - black)).round() as u16
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(output, expected, "{:?}", packing);
+        assert_eq!(metadata.levels, Some(Levels { black: [0.0; 3], white: 4095.0 }));
+    }
+}
+
+#[test]
+fn black_level_from_dark_frame() {
+    let interp = interp(4, 2, 12, BitPacking::MsbFirst);
+    // two dark frames, which average to 64 for red, 66 for green and 70 for blue
+    let dark_frames = [
+        pack(&interp, &[60, 64, 60, 64, 64, 70, 64, 70]),
+        pack(&interp, &[68, 68, 68, 68, 68, 70, 68, 70]),
+    ]
+    .concat();
+    let path = temp_file("dark-frame", &dark_frames);
+
+    let node = BlackWhiteLevel::from_parameters(
+        &parameters::<BlackWhiteLevel>(&[
+            ("dark-frame", string(path.to_str().unwrap())),
+            ("normalize", ParameterValue::BoolParameter(false)),
+        ]),
+        ProcessingContext::default(),
+    )
+    .unwrap();
+    let input = [100, 200, 300, 400, 500, 600, 700, 800];
+    let (output, metadata) = process(&node, raw_frame(interp, &input));
+    std::fs::remove_file(path).unwrap();
+
+    // without normalization only the metadata changes
+    assert_eq!(output, input);
+    assert_eq!(metadata.levels, Some(Levels { black: [64.0, 66.0, 70.0], white: 4095.0 }));
+}
+
+#[test]
+fn dark_frame_subtraction() {
+    let interp = interp(3, 3, 12, BitPacking::MsbFirst);
+    let dark_frames = [
+        pack(&interp, &[100, 110, 120, 130, 140, 150, 160, 170, 180]),
+        pack(&interp, &[102, 112, 122, 132, 142, 152, 162, 172, 182]),
+    ]
+    .concat();
+    let path = temp_file("master-dark", &dark_frames);
+    let input = [1000, 1000, 1000, 1000, 1000, 1000, 100, 4000, 4095];
+
+    let node = DarkFrameSubtraction::from_parameters(
+        &parameters::<DarkFrameSubtraction>(&[("dark-frame", string(path.to_str().unwrap()))]),
+        ProcessingContext::default(),
+    )
+    .unwrap();
+    let (output, _) = process(&node, raw_frame(interp, &input));
+    assert_eq!(output, [899, 889, 879, 869, 859, 849, 0, 3829, 3914]);
+
+    // half the exposure time of the dark frames, taken from the metadata, and an
+    // offset
+    let node = DarkFrameSubtraction::from_parameters(
+        &parameters::<DarkFrameSubtraction>(&[
+            ("dark-frame", string(path.to_str().unwrap())),
+            ("dark-frame-exposure-time", string("20")),
+            ("offset", ParameterValue::IntRange(16)),
+        ]),
+        ProcessingContext::default(),
+    )
+    .unwrap();
+    let lock = ProcessingStageLock::new();
+    let result = node.process(&mut raw_frame(interp, &input), lock.waiter_for(0));
+    assert!(result.is_err(), "the exposure time of the frames is needed for the scaling");
+
+    let metadata = FrameMetadata::default().with_tag("exposure-time", 10);
+    let frame = raw_frame_with_metadata(interp, &input, metadata);
+    let (output, _) = process(&node, frame);
+    std::fs::remove_file(path).unwrap();
+    // 1000 - 101 / 2 + 16 = 965.5, which is rounded up
+    assert_eq!(output, [966, 961, 956, 951, 946, 941, 36, 3931, 4021]);
+}
+
+#[test]
+fn flat_field_correction() {
+    let interp = interp(4, 2, 12, BitPacking::MsbFirst);
+    // after subtracting the dark frame, red averages to 1500, green to 2000 and
+    // blue to 1000
+    let flat_field = pack(&interp, &[1100, 1100, 2100, 3100, 1100, 600, 3100, 1600]);
+    let dark_frame = pack(&interp, &[100; 8]);
+    let flat_field_path = temp_file("flat-field", &flat_field);
+    let dark_frame_path = temp_file("flat-field-dark-frame", &dark_frame);
+
+    let node = FlatFieldCorrection::from_parameters(
+        &parameters::<FlatFieldCorrection>(&[
+            ("flat-field", string(flat_field_path.to_str().unwrap())),
+            ("flat-field-dark-frame", string(dark_frame_path.to_str().unwrap())),
+        ]),
+        ProcessingContext::default(),
+    )
+    .unwrap();
+    let (output, _) = process(&node, raw_frame(interp, &[300; 8]));
+    assert_eq!(output, [450, 600, 225, 200, 600, 600, 200, 200]);
+
+    // only the part above the black level is scaled
+    let levels = Levels { black: [100.0; 3], white: 4095.0 };
+    let metadata = FrameMetadata { levels: Some(levels), ..FrameMetadata::default() };
+    let (output, _) = process(&node, raw_frame_with_metadata(interp, &[300; 8], metadata));
+    std::fs::remove_file(flat_field_path).unwrap();
+    std::fs::remove_file(dark_frame_path).unwrap();
+    assert_eq!(output, [400, 500, 250, 233, 500, 500, 233, 233]);
+}
+
+// the output of dark_frame.glsl for 8 bit samples and 12 bit samples in 16 bit
+// words, with two dark frames that average to half values in places, and
+// results below zero and above the bit depth. calls `func` with the node
+// parameters, the input frame and the expected samples
+fn for_each_dark_frame_fixture(mut func: impl FnMut(&Parameters, Payload, &[u16])) {
+    let fixture = |name: &str| {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dark_frame").join(name);
+        (path.to_str().unwrap().to_string(), std::fs::read(&path).unwrap())
+    };
+    let cases = [
+        (interp(9, 7, 8, BitPacking::MsbFirst), "8bit", "darks_8bit.raw8", "raw8"),
+        (interp(9, 7, 12, BitPacking::U16Le), "12bit", "darks_12bit.raw12", "u16le"),
+    ];
+    for &(interp, bit_depth, darks, extension) in cases.iter() {
+        let (_, input) = fixture(&format!("input_{}.{}", bit_depth, extension));
+        let (darks, _) = fixture(darks);
+        let scaled = [
+            ("offset", ParameterValue::IntRange(8)),
+            ("dark-frame-exposure-time", string("2")),
+            ("exposure-time", string("1")),
+        ];
+        for &(name, values) in [("plain", &[][..]), ("scaled", &scaled[..])].iter() {
+            let mut parameters = parameters::<DarkFrameSubtraction>(values);
+            parameters.0.insert("dark-frame".to_string(), string(&darks));
+            parameters.0.insert("dark-frame-packing".to_string(), string("msb-first"));
+
+            let (_, expected) = fixture(&format!("output_{}_{}.{}", bit_depth, name, extension));
+            let mut samples = vec![];
+            interp.packing.read_samples(interp.bit_depth, &expected, |sample| samples.push(sample));
+            let input = raw_frame_from_bytes(interp, &input, FrameMetadata::default());
+            func(&parameters, input, &samples);
+        }
+    }
+}
+
+#[test]
+fn dark_frame_subtraction_matches_shader_fixtures() {
+    for_each_dark_frame_fixture(|parameters, input, expected| {
+        let node = DarkFrameSubtraction::from_parameters(parameters, ProcessingContext::default())
+            .unwrap();
+        assert_eq!(process(&node, input).0, expected);
+    });
+}
+
+// checks the fixtures against the shader
+#[cfg(feature = "gpu")]
+#[test]
+#[ignore = "needs a gpu"]
+fn gpu_dark_frame_subtraction_matches_fixtures() {
+    use recorder::nodes_gpu::dark_frame::GpuDarkFrameSubtraction;
+
+    for_each_dark_frame_fixture(|parameters, input, expected| {
+        let node =
+            GpuDarkFrameSubtraction::from_parameters(parameters, ProcessingContext::default())
+                .unwrap();
+        assert_eq!(process(&node, input).0, expected);
+    });
+}
+
+// run it on a machine with a gpu with `cargo test -- --ignored`
+#[cfg(feature = "gpu")]
+#[test]
+#[ignore = "needs a gpu"]
+fn cpu_dark_frame_subtraction_matches_gpu() {
+    use recorder::nodes_gpu::dark_frame::GpuDarkFrameSubtraction;
+
+    let context = ProcessingContext::default();
+    // the dark frames are packed, the frames are 16 bit words like after a
+    // GpuBitDepthConverter
+    let (width, height) = (70u64, 46u64);
+    let samples = (0..width * height).map(|i| (i * 7919 % 4096) as u16).collect::<Vec<_>>();
+    let dark = (0..width * height).map(|i| (i * 31 % 300) as u16).collect::<Vec<_>>();
+    let path =
+        temp_file("gpu-dark", &pack(&interp(width, height, 12, BitPacking::MsbFirst), &dark));
+    let parameters = parameters::<DarkFrameSubtraction>(&[
+        ("dark-frame", string(path.to_str().unwrap())),
+        ("dark-frame-packing", string("msb-first")),
+        ("offset", ParameterValue::IntRange(8)),
+    ]);
+
+    let input = raw_frame(interp(width, height, 12, BitPacking::U16Le), &samples);
+    let cpu = DarkFrameSubtraction::from_parameters(&parameters, context.clone()).unwrap();
+    let gpu = GpuDarkFrameSubtraction::from_parameters(&parameters, context).unwrap();
+    let (cpu_output, _) = process(&cpu, input.clone());
+    let (gpu_output, _) = process(&gpu, input);
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(cpu_output, gpu_output);
+}
@@ -0,0 +1,88 @@
+//! Frame and parameter factories shared by the integration tests
+
+// every test crate uses only some of these
+#![allow(dead_code)]
+
+use recorder::pipeline_processing::{
+    buffers::CpuBuffer,
+    execute::ProcessingStageLock,
+    frame::{BitPacking, Frame, FrameRate, Raw},
+    metadata::FrameMetadata,
+    parametrizable::{ParameterTypeDescriptor, ParameterValue, Parameterizable, Parameters},
+    payload::Payload,
+    processing_node::ProcessingNode,
+};
+
+/// The default parameters of a node, with `values` set in addition
+pub fn parameters<T: Parameterizable>(values: &[(&str, ParameterValue)]) -> Parameters {
+    let mut parameters = Parameters(
+        T::describe_parameters()
+            .0
+            .into_iter()
+            .filter_map(|(key, descriptor)| match descriptor {
+                ParameterTypeDescriptor::Optional(_, value) => Some((key, value)),
+                ParameterTypeDescriptor::Mandatory(_) => None,
+            })
+            .collect(),
+    );
+    for (key, value) in values {
+        parameters.0.insert(key.to_string(), value.clone());
+    }
+    parameters
+}
+
+pub fn string(value: &str) -> ParameterValue { ParameterValue::StringParameter(value.to_string()) }
+
+/// A raw interpretation with a RGGB bayer pattern
+pub fn interp(width: u64, height: u64, bit_depth: u64, packing: BitPacking) -> Raw {
+    Raw {
+        width,
+        height,
+        bit_depth,
+        packing,
+        cfa: "RGGB".parse().unwrap(),
+        fps: FrameRate::new(24, 1).unwrap(),
+    }
+}
+
+/// Deterministic bytes without any structure
+pub fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545f491u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// Packs the samples like `interp` describes
+pub fn pack(interp: &Raw, samples: &[u16]) -> Vec<u8> {
+    let mut data = vec![0; interp.packing.required_bytes(interp.bit_depth, samples.len())];
+    interp.packing.write_samples(interp.bit_depth, samples.iter().copied(), &mut data);
+    data
+}
+
+/// A raw frame in a cpu buffer with the already packed `data`
+pub fn raw_frame_from_bytes(interp: Raw, data: &[u8], metadata: FrameMetadata) -> Payload {
+    let mut storage = CpuBuffer::new_heap(data.len());
+    storage.as_mut_slice(|storage| storage.copy_from_slice(data));
+    Payload::from(Frame { interp, storage, metadata })
+}
+
+pub fn raw_frame_with_metadata(interp: Raw, samples: &[u16], metadata: FrameMetadata) -> Payload {
+    raw_frame_from_bytes(interp, &pack(&interp, samples), metadata)
+}
+
+pub fn raw_frame(interp: Raw, samples: &[u16]) -> Payload {
+    raw_frame_with_metadata(interp, samples, FrameMetadata::default())
+}
+
+/// Runs the node on a copy of `input` as the first frame and returns its
+/// output
+pub fn process(node: &dyn ProcessingNode, input: &Payload) -> Payload {
+    let lock = ProcessingStageLock::new();
+    node.process(&mut input.clone(), lock.waiter_for(0)).unwrap().unwrap()
+}
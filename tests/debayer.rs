@@ -0,0 +1,370 @@
+mod common;
+
+use common::{interp, parameters, string};
+use recorder::{
+    nodes_cpu::debayer::CpuDebayer,
+    pipeline_processing::{
+        execute::ProcessingStageLock,
+        frame::{BitPacking, CfaDescriptor, Raw, Rgb, Rgb16, RgbF16, RgbF32, RgbFormat},
+        parametrizable::{ParameterValue, Parameterizable, Parameters},
+        payload::Payload,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const ALGORITHMS: [&str; 3] = ["bilinear", "malvar", "hamilton-adams"];
+const RGB_FORMATS: [&str; 4] = ["rgb8", "rgb16", "rgbf16", "rgbf32"];
+// a frame with borders on every side of the 5x5 neighbourhood and one that is
+// smaller than it
+const FIXTURE_SIZES: [(u64, u64); 2] = [(9, 7), (3, 2)];
+
+fn with_algorithm<T: Parameterizable>(algorithm: &str) -> Parameters {
+    parameters::<T>(&[("algorithm", string(algorithm))])
+}
+
+// 8 bit samples or 12 bit samples in 16 bit words, which both debayer nodes
+// accept
+fn raw_frame(width: u64, height: u64, cfa: &str, packing: BitPacking, samples: &[u16]) -> Payload {
+    let bit_depth = if packing == BitPacking::U16Le { 12 } else { 8 };
+    let interp = Raw { cfa: cfa.parse().unwrap(), ..interp(width, height, bit_depth, packing) };
+    common::raw_frame(interp, samples)
+}
+
+fn debayer(node: &dyn ProcessingNode, context: &ProcessingContext, input: Payload) -> Vec<u8> {
+    let mut output = common::process(node, &input);
+    context.ensure_cpu_buffer::<Rgb>(&mut output).unwrap().storage.as_slice(|s| s.to_vec())
+}
+
+// debayers to the given output format and returns the values of all channels
+fn debayer_channels(
+    node: &dyn ProcessingNode,
+    context: &ProcessingContext,
+    input: Payload,
+    format: RgbFormat,
+) -> Vec<f64> {
+    let mut output = common::process(node, &input);
+    let bytes = match format {
+        RgbFormat::Rgb8 => {
+            return debayer(node, context, input).into_iter().map(f64::from).collect()
+        }
+        RgbFormat::Rgb16 => context
+            .ensure_cpu_buffer::<Rgb16>(&mut output)
+            .unwrap()
+            .storage
+            .as_slice(|s| s.to_vec()),
+        RgbFormat::RgbF16 => context
+            .ensure_cpu_buffer::<RgbF16>(&mut output)
+            .unwrap()
+            .storage
+            .as_slice(|s| s.to_vec()),
+        RgbFormat::RgbF32 => context
+            .ensure_cpu_buffer::<RgbF32>(&mut output)
+            .unwrap()
+            .storage
+            .as_slice(|s| s.to_vec()),
+    };
+    match format {
+        RgbFormat::RgbF32 => bytes
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f64)
+            .collect(),
+        _ => bytes
+            .chunks_exact(2)
+            .map(|value| u16::from_le_bytes([value[0], value[1]]))
+            .map(|value| if format == RgbFormat::RgbF16 { f16_to_f64(value) } else { value as f64 })
+            .collect(),
+    }
+}
+
+// only for the positive values the debayer nodes produce
+fn f16_to_f64(bits: u16) -> f64 {
+    let exponent = (bits >> 10) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+// a frame where every color has the same value everywhere, so the
+// interpolation has to reproduce exactly these values
+fn flat_frame(
+    width: u64,
+    height: u64,
+    cfa: &str,
+    values: [u16; 3],
+    packing: BitPacking,
+) -> Payload {
+    let descriptor: CfaDescriptor = cfa.parse().unwrap();
+    let samples = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| values[descriptor.color_at(x, y).unwrap() as usize])
+        .collect::<Vec<_>>();
+    raw_frame(width, height, cfa, packing, &samples)
+}
+
+#[test]
+fn cpu_debayer_reproduces_flat_colors() {
+    let context = ProcessingContext::default();
+    for algorithm in ALGORITHMS {
+        let parameters = with_algorithm::<CpuDebayer>(algorithm);
+        let node = CpuDebayer::from_parameters(&parameters, context.clone()).unwrap();
+
+        for cfa in ["RGGB", "GRBG", "GBRG", "BGGR"] {
+            let rgb = debayer(
+                &node,
+                &context,
+                flat_frame(6, 4, cfa, [200, 100, 40], BitPacking::MsbFirst),
+            );
+            for pixel in rgb.chunks_exact(3) {
+                assert_eq!(pixel, [200, 100, 40], "{} {}", algorithm, cfa);
+            }
+        }
+
+        // 12 bit samples in 16 bit words get scaled to 8 bit
+        let rgb =
+            debayer(&node, &context, flat_frame(6, 4, "RGGB", [4095, 0, 2047], BitPacking::U16Le));
+        for pixel in rgb.chunks_exact(3) {
+            assert_eq!(pixel, [255, 0, 127], "{}", algorithm);
+        }
+    }
+}
+
+#[test]
+fn only_bilinear_handles_other_patterns() {
+    let context = ProcessingContext::default();
+    let quad_bayer = flat_frame(8, 8, "RRGG/RRGG/GGBB/GGBB", [200, 100, 40], BitPacking::MsbFirst);
+    for algorithm in ALGORITHMS {
+        let parameters = with_algorithm::<CpuDebayer>(algorithm);
+        let node = CpuDebayer::from_parameters(&parameters, context.clone()).unwrap();
+        let lock = ProcessingStageLock::new();
+        let result = node.process(&mut quad_bayer.clone(), lock.waiter_for(0));
+        assert_eq!(result.is_ok(), algorithm == "bilinear", "{}", algorithm);
+    }
+}
+
+// an edge between a dark and a bright area: the edge aware algorithm may not
+// produce colors that are brighter than both sides
+#[test]
+fn edge_aware_debayer_stays_within_the_edge() {
+    let context = ProcessingContext::default();
+    let parameters = with_algorithm::<CpuDebayer>("hamilton-adams");
+    let node = CpuDebayer::from_parameters(&parameters, context.clone()).unwrap();
+
+    let (width, height) = (8u64, 8u64);
+    let samples = (0..height)
+        .flat_map(|_| (0..width).map(|x| if x < 4 { 20 } else { 220 }))
+        .collect::<Vec<u16>>();
+    let rgb =
+        debayer(&node, &context, raw_frame(width, height, "RGGB", BitPacking::MsbFirst, &samples));
+    for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+        let x = i as u64 % width;
+        let expected = if x < 4 { 20 } else { 220 };
+        assert_eq!(pixel, [expected; 3], "pixel {}x{}", x, i as u64 / width);
+    }
+}
+
+#[test]
+fn cpu_debayer_passes_mono_through() {
+    let context = ProcessingContext::default();
+    let node =
+        CpuDebayer::from_parameters(&parameters::<CpuDebayer>(&[]), context.clone()).unwrap();
+
+    let samples = (0..24).map(|v| v * 10).collect::<Vec<u16>>();
+    let rgb = debayer(&node, &context, raw_frame(6, 4, "mono", BitPacking::MsbFirst, &samples));
+    for (pixel, sample) in rgb.chunks_exact(3).zip(samples) {
+        assert_eq!(pixel, [sample as u8; 3]);
+    }
+}
+
+#[test]
+fn cpu_debayer_unpacks_every_packing() {
+    let context = ProcessingContext::default();
+    let node =
+        CpuDebayer::from_parameters(&parameters::<CpuDebayer>(&[]), context.clone()).unwrap();
+
+    let samples = (0..7 * 5).map(|v| v * 117 % 4096).collect::<Vec<u16>>();
+    let expected = debayer(&node, &context, raw_frame(7, 5, "RGGB", BitPacking::U16Le, &samples));
+    for &packing in [BitPacking::MsbFirst, BitPacking::LsbFirst].iter() {
+        let packed = common::raw_frame(interp(7, 5, 12, packing), &samples);
+        assert_eq!(debayer(&node, &context, packed), expected, "{:?}", packing);
+    }
+}
+
+#[test]
+fn cpu_debayer_output_formats() {
+    let context = ProcessingContext::default();
+    let input = flat_frame(6, 4, "RGGB", [255, 0, 100], BitPacking::MsbFirst);
+    let expected = [
+        [255.0, 0.0, 100.0],
+        [65535.0, 0.0, 25700.0],
+        // the half float closest to 100 / 255
+        [1.0, 0.0, f16_to_f64(0x3646)],
+        [1.0, 0.0, (100.0 * (1.0f32 / 255.0)) as f64],
+    ];
+    for (&format, expected) in RGB_FORMATS.iter().zip(expected.iter()) {
+        let parameters = parameters::<CpuDebayer>(&[("output-format", string(format))]);
+        let node = CpuDebayer::from_parameters(&parameters, context.clone()).unwrap();
+        let channels = debayer_channels(&node, &context, input.clone(), format.parse().unwrap());
+        for pixel in channels.chunks_exact(3) {
+            assert_eq!(pixel, expected, "{}", format);
+        }
+    }
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/debayer").join(name)
+}
+
+// the output of debayer.glsl as f32 for 12 bit samples in 16 bit words, for
+// every bayer phase of every algorithm and the other patterns of bilinear.
+// calls `func` with the node parameters, the input frame and the name of the
+// fixture
+fn for_each_fixture(mut func: impl FnMut(&[(&str, ParameterValue)], Payload, &str)) {
+    for &(width, height) in FIXTURE_SIZES.iter() {
+        let input = fs::read(fixture_path(&format!("input_{}x{}.u16le", width, height))).unwrap();
+        let samples = input
+            .chunks_exact(2)
+            .map(|word| u16::from_le_bytes([word[0], word[1]]))
+            .collect::<Vec<_>>();
+
+        for algorithm in ALGORITHMS {
+            let bilinear_only = ["RRGG/RRGG/GGBB/GGBB", "RGB/GBR/BRG", "mono"];
+            let others = if algorithm == "bilinear" { &bilinear_only[..] } else { &[] };
+            for &cfa in ["RGGB", "GRBG", "GBRG", "BGGR"].iter().chain(others) {
+                let values =
+                    [("algorithm", string(algorithm)), ("output-format", string("rgbf32"))];
+                let name =
+                    format!("{}_{}_{}x{}.rgbf32", algorithm, cfa.replace('/', "-"), width, height);
+                func(&values, raw_frame(width, height, cfa, BitPacking::U16Le, &samples), &name);
+            }
+        }
+    }
+}
+
+fn assert_matches_fixture(channels: &[f64], name: &str) {
+    let expected = fs::read(fixture_path(name)).unwrap();
+    assert_eq!(channels.len() * 4, expected.len(), "{}", name);
+    let expected = expected.chunks_exact(4).map(|v| f32::from_le_bytes([v[0], v[1], v[2], v[3]]));
+    for (i, (value, expected)) in channels.iter().zip(expected).enumerate() {
+        // leaves room for the gpu rounding float operations differently
+        assert!(
+            (value - expected as f64).abs() <= 1e-5,
+            "{}: pixel {} channel {}: {} instead of {}",
+            name,
+            i / 3,
+            i % 3,
+            value,
+            expected
+        );
+    }
+}
+
+#[test]
+fn cpu_debayer_matches_shader_fixtures() {
+    let context = ProcessingContext::default();
+    for_each_fixture(|values, input, name| {
+        let parameters = parameters::<CpuDebayer>(values);
+        let node = CpuDebayer::from_parameters(&parameters, context.clone()).unwrap();
+        assert_matches_fixture(&debayer_channels(&node, &context, input, RgbFormat::RgbF32), name);
+    });
+}
+
+// checks the fixtures against the shader. with UPDATE_FIXTURES set, it writes
+// the output of the shader to them instead, e.g. after changing debayer.glsl
+#[cfg(feature = "gpu")]
+#[test]
+#[ignore = "needs a gpu"]
+fn gpu_debayer_matches_fixtures() {
+    use recorder::nodes_gpu::debayer::Debayer;
+
+    let context = ProcessingContext::default();
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    for_each_fixture(|values, input, name| {
+        let node =
+            Debayer::from_parameters(&parameters::<Debayer>(values), context.clone()).unwrap();
+        let channels = debayer_channels(&node, &context, input, RgbFormat::RgbF32);
+        if update {
+            let bytes = channels.iter().flat_map(|&v| (v as f32).to_le_bytes()).collect::<Vec<_>>();
+            fs::write(fixture_path(name), bytes).unwrap();
+        } else {
+            assert_matches_fixture(&channels, name);
+        }
+    });
+}
+
+// the gpu and the cpu debayer have to produce the same images. run it on a
+// machine with a gpu with `cargo test -- --ignored`
+#[cfg(feature = "gpu")]
+#[test]
+#[ignore = "needs a gpu"]
+fn cpu_debayer_matches_gpu() {
+    use common::noise;
+    use itertools::Itertools;
+    use recorder::{nodes_cpu::debayer::DemosaicAlgorithm, nodes_gpu::debayer::Debayer};
+
+    let context = ProcessingContext::default();
+    // not a multiple of the 32x32 blocks the shader runs in
+    let (width, height) = (70u64, 46u64);
+    let samples_8bit =
+        noise((width * height) as usize).into_iter().map(u16::from).collect::<Vec<_>>();
+    let samples_12bit = noise((width * height) as usize * 2)
+        .chunks_exact(2)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]) % 4096)
+        .collect::<Vec<_>>();
+
+    for (algorithm, format) in ALGORITHMS.iter().cartesian_product(RGB_FORMATS.iter()) {
+        let values = [("algorithm", string(algorithm)), ("output-format", string(format))];
+        let cpu = CpuDebayer::from_parameters(&parameters::<CpuDebayer>(&values), context.clone())
+            .unwrap();
+        let gpu =
+            Debayer::from_parameters(&parameters::<Debayer>(&values), context.clone()).unwrap();
+        let format = format.parse::<RgbFormat>().unwrap();
+        // the gpu may round float operations differently, which can flip the
+        // truncation to the next integer or the rounding to the next half float
+        let tolerance = match format {
+            RgbFormat::Rgb8 | RgbFormat::Rgb16 => 1.0,
+            RgbFormat::RgbF16 => 1e-3,
+            RgbFormat::RgbF32 => 1e-5,
+        };
+
+        for cfa in ["RGGB", "GBRG", "RRGG/RRGG/GGBB/GGBB", "RGB/GBR/BRG", "mono"] {
+            let supported = algorithm
+                .parse::<DemosaicAlgorithm>()
+                .unwrap()
+                .check_cfa(cfa.parse().unwrap())
+                .is_ok();
+            if !supported {
+                continue;
+            }
+            for (packing, samples) in
+                [(BitPacking::MsbFirst, &samples_8bit), (BitPacking::U16Le, &samples_12bit)]
+            {
+                let input = raw_frame(width, height, cfa, packing, samples);
+                let cpu_rgb = debayer_channels(&cpu, &context, input.clone(), format);
+                let gpu_rgb = debayer_channels(&gpu, &context, input, format);
+
+                for (i, (cpu_value, gpu_value)) in cpu_rgb.iter().zip(gpu_rgb.iter()).enumerate() {
+                    assert!(
+                        (cpu_value - gpu_value).abs() <= tolerance,
+                        "{} {:?} {} {:?}: pixel {}x{} channel {}: cpu {} gpu {}",
+                        algorithm,
+                        format,
+                        cfa,
+                        packing,
+                        i as u64 / 3 % width,
+                        i as u64 / 3 / width,
+                        i % 3,
+                        cpu_value,
+                        gpu_value
+                    );
+                }
+            }
+        }
+    }
+}
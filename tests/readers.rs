@@ -0,0 +1,54 @@
+mod common;
+
+use common::parameters;
+use recorder::{
+    nodes_io::reader_raw::RawDirectoryReader,
+    pipeline_processing::{
+        execute::ProcessingStageLock,
+        frame::Raw,
+        parametrizable::{ParameterValue, Parameterizable},
+        payload::Payload,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
+};
+use std::fs;
+
+#[test]
+fn raw_directory_reader_records_missing_frames() {
+    let directory =
+        std::env::temp_dir().join(format!("recorder-test-{}-frames", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    for number in [1, 2, 5, 6] {
+        fs::write(directory.join(format!("frame_{:04}.raw8", number)), [number as u8]).unwrap();
+    }
+
+    let pattern = directory.join("frame_*.raw8");
+    let node = RawDirectoryReader::from_parameters(
+        &parameters::<RawDirectoryReader>(&[
+            (
+                "file-pattern",
+                ParameterValue::StringParameter(pattern.to_str().unwrap().to_string()),
+            ),
+            ("bit-depth", ParameterValue::IntRange(8)),
+            ("width", ParameterValue::IntRange(1)),
+            ("height", ParameterValue::IntRange(1)),
+        ]),
+        ProcessingContext::default(),
+    )
+    .unwrap();
+
+    let context = ProcessingContext::default();
+    let lock = ProcessingStageLock::new();
+    let metadata = (0..4)
+        .map(|frame| {
+            let mut output =
+                node.process(&mut Payload::empty(), lock.waiter_for(frame)).unwrap().unwrap();
+            let frame = context.ensure_cpu_buffer::<Raw>(&mut output).unwrap();
+            (frame.metadata.sequence_number, frame.metadata.dropped_before)
+        })
+        .collect::<Vec<_>>();
+    fs::remove_dir_all(directory).unwrap();
+
+    assert_eq!(metadata, [(Some(1), 0), (Some(2), 0), (Some(5), 2), (Some(6), 0)]);
+}
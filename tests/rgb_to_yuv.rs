@@ -0,0 +1,34 @@
+mod common;
+
+use common::parameters;
+use recorder::{
+    nodes_cpu::rgb_to_yuv::RgbToYuv,
+    pipeline_processing::{
+        buffers::CpuBuffer,
+        execute::ProcessingStageLock,
+        frame::{Frame, FrameRate, Rgb},
+        metadata::FrameMetadata,
+        parametrizable::Parameterizable,
+        payload::Payload,
+        processing_context::ProcessingContext,
+        processing_node::ProcessingNode,
+    },
+};
+
+fn rgb_frame(width: u64, height: u64) -> Payload {
+    let interp = Rgb { width, height, fps: FrameRate::new(24, 1).unwrap() };
+    let storage = CpuBuffer::new_heap((width * height * 3) as usize);
+    Payload::from(Frame { interp, storage, metadata: FrameMetadata::default() })
+}
+
+#[test]
+fn rgb_to_yuv_rejects_empty_frames() {
+    let node =
+        RgbToYuv::from_parameters(&parameters::<RgbToYuv>(&[]), ProcessingContext::default())
+            .unwrap();
+    for (width, height) in [(0, 0), (0, 4), (4, 0)] {
+        let lock = ProcessingStageLock::new();
+        let result = node.process(&mut rgb_frame(width, height), lock.waiter_for(0));
+        assert!(result.is_err(), "{}x{} frames have no rows to convert", width, height);
+    }
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::{interp, noise, parameters, raw_frame_from_bytes, string};
+use recorder::{
+    nodes_cpu::bitdepth_convert::BitDepthConverter,
+    pipeline_processing::{
+        buffers::CpuBuffer,
+        frame::{BitPacking, Frame, FrameInterpretation, Raw},
+        metadata::FrameMetadata,
+        parametrizable::{ParameterValue, Parameterizable},
+        port_type::PortType,
+        processing_context::ProcessingContext,
+        unpack::{unpack, unpack_rows, unpack_scalar},
+    },
+};
+
+const PACKINGS: [BitPacking; 3] = [BitPacking::MsbFirst, BitPacking::LsbFirst, BitPacking::U16Le];
+
+#[test]
+fn unpack_matches_scalar_unpacking() {
+    for &packing in PACKINGS.iter() {
+        for bit_depth in 1..=16 {
+            // lengths around the 16 byte loads of the simd path
+            for &samples in [0, 1, 7, 8, 9, 15, 16, 17, 31, 64, 1001].iter() {
+                let input = noise(packing.required_bytes(bit_depth, samples));
+                let mut expected = vec![0; samples];
+                unpack_scalar(packing, bit_depth, &input, &mut expected);
+                let mut output = vec![0; samples];
+                unpack(packing, bit_depth, &input, &mut output);
+                assert_eq!(
+                    output, expected,
+                    "{:?} {} bit, {} samples",
+                    packing, bit_depth, samples
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn unpack_rows_covers_the_whole_frame() {
+    for &packing in PACKINGS.iter() {
+        for &bit_depth in [8, 10, 12, 14, 16].iter() {
+            // odd widths, where rows don't start at byte boundaries
+            for &(width, height) in [(16, 16), (13, 7), (1, 3), (101, 33)].iter() {
+                let interp = interp(width, height, bit_depth, packing);
+                let input = noise(interp.required_bytes());
+                let mut expected = vec![0; (width * height) as usize];
+                unpack_scalar(packing, bit_depth, &input, &mut expected);
+
+                let mut output = vec![0u8; expected.len() * 2];
+                unpack_rows(&interp, &input, &mut output, 16, |_, samples, output| {
+                    for (&sample, output) in samples.iter().zip(output.chunks_exact_mut(2)) {
+                        output.copy_from_slice(&sample.to_le_bytes());
+                    }
+                });
+                let output: Vec<u16> = output
+                    .chunks_exact(2)
+                    .map(|word| u16::from_le_bytes([word[0], word[1]]))
+                    .collect();
+                assert_eq!(
+                    output, expected,
+                    "{:?} {} bit, {}x{}",
+                    packing, bit_depth, width, height
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn bit_depth_converter_matches_scalar_unpacking() {
+    let context = ProcessingContext::default();
+    for &packing in PACKINGS[..2].iter() {
+        for &bit_depth in [10, 12, 14].iter() {
+            let interp = interp(37, 11, bit_depth, packing);
+            let data = noise(interp.required_bytes());
+            let mut samples = vec![0; (interp.width * interp.height) as usize];
+            unpack_scalar(packing, bit_depth, &data, &mut samples);
+            let input = raw_frame_from_bytes(interp, &data, FrameMetadata::default());
+
+            for &(output, left_align) in [("8bit", false), ("16bit", false), ("16bit", true)].iter()
+            {
+                let parameters = parameters::<BitDepthConverter>(&[
+                    ("output", string(output)),
+                    ("left-align", ParameterValue::BoolParameter(left_align)),
+                ]);
+                let node =
+                    BitDepthConverter::from_parameters(&parameters, context.clone()).unwrap();
+                let output_payload = common::process(&node, &input);
+                let frame = output_payload.downcast::<Frame<Raw, CpuBuffer>>().unwrap();
+                // the type the pipeline is checked with before it starts
+                assert_eq!(
+                    BitDepthConverter::output_type(&parameters, PortType::raw(bit_depth)).unwrap(),
+                    PortType::raw(frame.interp.bit_depth)
+                );
+
+                let mut converted = vec![0; samples.len()];
+                unpack_scalar(
+                    frame.interp.packing,
+                    frame.interp.bit_depth,
+                    &frame.storage.as_slice(|s| s.to_vec()),
+                    &mut converted,
+                );
+                let expected: Vec<u16> = samples
+                    .iter()
+                    .map(|&sample| match (output, left_align) {
+                        ("8bit", _) => sample >> (bit_depth - 8),
+                        (_, false) => sample,
+                        (_, true) => sample << (16 - bit_depth),
+                    })
+                    .collect();
+                assert_eq!(
+                    converted, expected,
+                    "{:?} {} bit to {} {}",
+                    packing, bit_depth, output, left_align
+                );
+            }
+        }
+    }
+}